@@ -17,4 +17,46 @@ pub enum ErrorCode {
     InvalidClaimAmount,
     #[msg("Insufficient balance to claim")]
     InsufficientBalanceClaim,
+    #[msg("Order book is full")]
+    OrderBookFull,
+    #[msg("Duplicate order ID")]
+    DuplicateOrderId,
+    #[msg("Event queue is full")]
+    EventQueueFull,
+    #[msg("Account is not an OpenOrders account of this market")]
+    InvalidOpenOrders,
+    #[msg("OpenOrders account passed more than once")]
+    DuplicateOpenOrders,
+    #[msg("Post-only order would cross the book")]
+    PostOnlyWouldCross,
+    #[msg("Fill-or-kill order could not be fully filled")]
+    FillOrKillNotFilled,
+    #[msg("Tick size, lot sizes and order limits must be non-zero and within bounds")]
+    InvalidMarketConfig,
+    #[msg("Price must be a positive multiple of the tick size")]
+    InvalidPrice,
+    #[msg("Order size is below the market minimum")]
+    OrderTooSmall,
+    #[msg("Fees and the referrer share must be at most 100%, and a maker rebate no larger than the taker fee")]
+    InvalidFeeConfig,
+    #[msg("No fees to collect")]
+    NoFeesToCollect,
+    #[msg("Order would trade against the signer's own resting order")]
+    WouldSelfTrade,
+    #[msg("Expiry timestamp is in the past")]
+    InvalidExpiry,
+    #[msg("Deposit amount must be greater than zero")]
+    InvalidDepositAmount,
+    #[msg("OpenOrders account still has resting orders or a balance")]
+    OpenOrdersNotEmpty,
+    #[msg("User has reached the market's limit of resting orders")]
+    TooManyOpenOrders,
+    #[msg("Market is not accepting new orders")]
+    MarketNotActive,
+    #[msg("Market is paused")]
+    MarketPaused,
+    #[msg("Tick and lot sizes can only change while the books and event queue are empty")]
+    MarketNotEmpty,
+    #[msg("Referrer can't be the market's own quote vault")]
+    InvalidReferrer,
 }
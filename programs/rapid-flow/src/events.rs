@@ -0,0 +1,119 @@
+use anchor_lang::prelude::*;
+
+use crate::{Market, MarketConfig, Order, OrderBookFullPolicy};
+
+// Every event carries the market's next event sequence number, so a
+// consumer that sees a gap knows it missed one. Prices are in quote lots per
+// base lot and sizes in base lots, as on the book.
+
+#[event]
+pub struct MarketInitialized {
+    pub market: Pubkey,
+    pub seq_num: u64,
+    pub authority: Pubkey,
+    pub base_mint: Pubkey,
+    pub quote_mint: Pubkey,
+    pub tick_size: u64,
+    pub base_lot_size: u64,
+    pub quote_lot_size: u64,
+    pub min_base_order_size: u64,
+    pub max_orders_per_user: u8,
+    pub maker_fee_bps: i16,
+    pub taker_fee_bps: u16,
+    pub referrer_share_bps: u16,
+    pub book_full_policy: OrderBookFullPolicy,
+}
+
+// An order came to rest on the book
+#[event]
+pub struct OrderPlaced {
+    pub market: Pubkey,
+    pub seq_num: u64,
+    pub owner: Pubkey,
+    pub order_id: u128,
+    pub client_order_id: u64,
+    pub is_bid: bool,
+    pub price: u64,
+    pub size: u64,
+    pub expiry_timestamp: i64,
+}
+
+#[event]
+pub struct OrderFilled {
+    pub market: Pubkey,
+    pub seq_num: u64,
+    pub maker: Pubkey,
+    pub taker: Pubkey,
+    pub maker_order_id: u128,
+    pub taker_is_bid: bool,
+    pub price: u64,
+    pub size: u64,
+    pub taker_fee: u64, // quote tokens
+    pub maker_fee: i64, // quote tokens, negative for a rebate
+}
+
+// Some or all of a resting order left the book without trading: cancelled,
+// edited, expired, evicted or dropped to prevent a self-trade
+#[event]
+pub struct OrderCancelled {
+    pub market: Pubkey,
+    pub seq_num: u64,
+    pub owner: Pubkey,
+    pub order_id: u128,
+    pub is_bid: bool,
+    pub size: u64, // base lots removed
+}
+
+// Emits `OrderCancelled` for `size` base lots of `order`
+pub fn emit_order_cancelled(market: &mut Account<Market>, order: &Order, size: u64) {
+    emit!(OrderCancelled {
+        market: market.key(),
+        seq_num: market.next_event_seq_num(),
+        owner: order.owner,
+        order_id: order.order_id,
+        is_bid: Order::is_bid_order_id(order.order_id),
+        size,
+    });
+}
+
+#[event]
+pub struct FundsSettled {
+    pub market: Pubkey,
+    pub seq_num: u64,
+    pub owner: Pubkey,
+    pub base_amount: u64,
+    pub quote_amount: u64,
+}
+
+#[event]
+pub struct MarketConfigUpdated {
+    pub market: Pubkey,
+    pub seq_num: u64,
+    pub old_config: MarketConfig,
+    pub new_config: MarketConfig,
+}
+
+#[event]
+pub struct AuthorityProposed {
+    pub market: Pubkey,
+    pub seq_num: u64,
+    pub authority: Pubkey,
+    pub pending_authority: Pubkey, // default when a proposal is withdrawn
+}
+
+#[event]
+pub struct AuthorityTransferred {
+    pub market: Pubkey,
+    pub seq_num: u64,
+    pub old_authority: Pubkey,
+    pub new_authority: Pubkey,
+}
+
+#[event]
+pub struct ReferrerFeePaid {
+    pub market: Pubkey,
+    pub seq_num: u64,
+    pub referrer: Pubkey, // quote token account paid
+    pub taker: Pubkey,
+    pub amount: u64,
+}
@@ -0,0 +1,34 @@
+use anchor_lang::prelude::*;
+
+use crate::{error::ErrorCode, events::*, *};
+
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    pub new_authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"market", market.base_mint.key().as_ref(), market.quote_mint.key().as_ref()],
+        bump,
+        constraint = market.pending_authority == new_authority.key() @ ErrorCode::UnauthorizedAccess
+    )]
+    pub market: Account<'info, Market>,
+}
+
+impl<'info> AcceptAuthority<'info> {
+    // Completes a transfer proposed by the current authority
+    pub fn accept_authority(&mut self) -> Result<()> {
+        let old_authority = self.market.authority;
+        self.market.authority = self.new_authority.key();
+        self.market.pending_authority = Pubkey::default();
+
+        emit!(AuthorityTransferred {
+            market: self.market.key(),
+            seq_num: self.market.next_event_seq_num(),
+            old_authority,
+            new_authority: self.new_authority.key(),
+        });
+
+        Ok(())
+    }
+}
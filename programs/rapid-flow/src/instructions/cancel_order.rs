@@ -5,7 +5,7 @@ use anchor_spl::{
     token::{transfer, Token, TokenAccount, Transfer},
 };
 
-use crate::{error::ErrorCode, *};
+use crate::{error::ErrorCode, events::*, *};
 
 #[derive(Accounts)]
 pub struct CancelOrder<'info> {
@@ -19,19 +19,11 @@ pub struct CancelOrder<'info> {
     )]
     pub market: Account<'info, Market>,
 
-    #[account(
-        mut,
-        seeds = [b"bids", market.key().as_ref()],
-        bump
-    )]
-    pub bids: Account<'info, OrderBook>,
+    #[account(mut, address = market.bids)]
+    pub bids: AccountLoader<'info, OrderBook>,
 
-    #[account(
-        mut,
-        seeds = [b"asks", market.key().as_ref()],
-        bump
-    )]
-    pub asks: Account<'info, OrderBook>,
+    #[account(mut, address = market.asks)]
+    pub asks: AccountLoader<'info, OrderBook>,
 
     #[account(
         mut,
@@ -39,7 +31,7 @@ pub struct CancelOrder<'info> {
         bump,
         constraint = open_orders.owner == signer.key() @ ErrorCode::UnauthorizedAccess
     )]
-    pub open_orders: Account<'info, OpenOrders>,
+    pub open_orders: Box<Account<'info, OpenOrders>>,
 
     #[account(
         mut,
@@ -79,32 +71,123 @@ pub struct CancelOrder<'info> {
 }
 
 impl<'info> CancelOrder<'info> {
+    // Cancels the signer's resting order carrying `client_order_id`
+    pub fn cancel_order_by_client_id(&mut self, client_order_id: u64) -> Result<()> {
+        // A filled order keeps its slot until its event is consumed, so only
+        // orders still on the book count
+        let bids = self.bids.load()?;
+        let asks = self.asks.load()?;
+        let (order_id, is_bid) = self
+            .open_orders
+            .open_orders()
+            .filter(|slot| slot.client_order_id == client_order_id)
+            .find(|slot| {
+                let order_book = if slot.is_bid { &bids } else { &asks };
+                order_book.find_order(slot.order_id).is_some()
+            })
+            .map(|slot| (slot.order_id, slot.is_bid))
+            .ok_or(ErrorCode::OrderNotFound)?;
+        drop(bids);
+        drop(asks);
+
+        self.cancel_order(order_id, is_bid)
+    }
+
     // Select the side of the orderbook to search (bids or asks)
     pub fn cancel_order(&mut self, order_id: u128, is_bid: bool) -> Result<()> {
-        let order_book = if is_bid {
-            &mut self.bids
+        require!(
+            self.market.status != MarketStatus::Paused,
+            ErrorCode::MarketPaused
+        );
+        let mut order_book = if is_bid {
+            self.bids.load_mut()?
         } else {
-            &mut self.asks
+            self.asks.load_mut()?
         };
 
-        let order_index = order_book
-            .orders
-            .iter()
-            .position(|o| o.order_id == order_id && o.owner == self.signer.key())
+        require!(
+            order_book
+                .find_order(order_id)
+                .is_some_and(|o| o.owner == self.signer.key()),
+            ErrorCode::OrderNotFound
+        );
+
+        let order = order_book
+            .remove_order(order_id)
             .ok_or(ErrorCode::OrderNotFound)?;
+        drop(order_book);
+        self.open_orders.remove_order(order_id);
+        emit_order_cancelled(&mut self.market, &order, order.size);
 
-        let order = order_book.orders.remove(order_index);
+        let refund_amount = self.market.order_locked_amount(&order, order.size)?;
+        self.refund_locked(is_bid, refund_amount)
+    }
 
-        let refund_amount = if is_bid {
-            order
-                .price
-                .checked_mul(order.size)
-                .ok_or(ErrorCode::MathOverflow)?
-        } else {
-            order.size
-        };
+    // Cancels up to `limit` of the signer's orders, bids then asks and best
+    // price first, on one side of the book if `is_bid` is given, refunding
+    // what they had locked
+    pub fn cancel_all_orders(&mut self, is_bid: Option<bool>, limit: u8) -> Result<()> {
+        require!(
+            self.market.status != MarketStatus::Paused,
+            ErrorCode::MarketPaused
+        );
+        let mut slots: Vec<OpenOrderSlot> = self
+            .open_orders
+            .open_orders()
+            .filter(|slot| is_bid.is_none_or(|is_bid| is_bid == slot.is_bid))
+            .copied()
+            .collect();
+        // Ask order IDs ascend from the best order and bid order IDs descend,
+        // so flip the bids
+        slots.sort_by_key(|slot| {
+            if slot.is_bid {
+                (0, !slot.order_id)
+            } else {
+                (1, slot.order_id)
+            }
+        });
+
+        let mut bids = self.bids.load_mut()?;
+        let mut asks = self.asks.load_mut()?;
+        let mut remaining = limit as usize;
+        let mut base_refund: u64 = 0;
+        let mut quote_refund: u64 = 0;
+
+        for slot in slots.iter() {
+            if remaining == 0 {
+                break;
+            }
+            let order_book = if slot.is_bid { &mut bids } else { &mut asks };
+            let Some(order) = order_book.remove_order(slot.order_id) else {
+                continue;
+            };
+            self.open_orders.remove_order(order.order_id);
+            emit_order_cancelled(&mut self.market, &order, order.size);
+
+            let locked_amount = self.market.order_locked_amount(&order, order.size)?;
+            let refund = if slot.is_bid {
+                &mut quote_refund
+            } else {
+                &mut base_refund
+            };
+            *refund = refund
+                .checked_add(locked_amount)
+                .ok_or(ErrorCode::MathOverflow)?;
+            remaining -= 1;
+        }
+        drop(bids);
+        drop(asks);
+
+        self.refund_locked(true, quote_refund)?;
+        self.refund_locked(false, base_refund)
+    }
 
-        let market_key = self.market.key();
+    // Returns funds locked behind cancelled bids (quote) or asks (base) to the
+    // signer's token account
+    fn refund_locked(&mut self, is_bid: bool, refund_amount: u64) -> Result<()> {
+        if refund_amount == 0 {
+            return Ok(());
+        }
 
         let seeds = &[
             b"market",
@@ -146,7 +229,7 @@ impl<'info> CancelOrder<'info> {
                 .checked_sub(refund_amount)
                 .ok_or(ErrorCode::InsufficientFunds)?;
         }
-        
+
         Ok(())
     }
 }
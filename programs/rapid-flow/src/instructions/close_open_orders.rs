@@ -0,0 +1,45 @@
+use anchor_lang::prelude::*;
+
+use crate::{error::ErrorCode, *};
+
+#[derive(Accounts)]
+pub struct CloseOpenOrders<'info> {
+    pub signer: Signer<'info>,
+
+    #[account(
+        seeds = [b"market", market.base_mint.key().as_ref(), market.quote_mint.key().as_ref()],
+        bump
+    )]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        mut,
+        seeds = [b"user_open_orders", market.key().as_ref(), signer.key().as_ref()],
+        bump = open_orders.bump,
+        constraint = open_orders.owner == signer.key() @ ErrorCode::UnauthorizedAccess,
+        close = destination
+    )]
+    pub open_orders: Box<Account<'info, OpenOrders>>,
+
+    /// CHECK: only receives the account's rent, wherever the owner chooses
+    #[account(mut)]
+    pub destination: UncheckedAccount<'info>,
+}
+
+impl<'info> CloseOpenOrders<'info> {
+    // Every resting order locks funds, and a maker's are only released once
+    // its fill and out events are consumed, so nothing locked also means no
+    // resting orders and no events still waiting on this account
+    pub fn close_open_orders(&self) -> Result<()> {
+        let open_orders = &self.open_orders;
+        require!(
+            open_orders.base_free == 0
+                && open_orders.base_locked == 0
+                && open_orders.quote_free == 0
+                && open_orders.quote_locked == 0
+                && open_orders.open_orders().next().is_none(),
+            ErrorCode::OpenOrdersNotEmpty
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,58 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{transfer, Token, TokenAccount, Transfer};
+
+use crate::{error::ErrorCode, *};
+
+#[derive(Accounts)]
+pub struct CollectFees<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"market", market.base_mint.key().as_ref(), market.quote_mint.key().as_ref()],
+        bump,
+        has_one = authority @ ErrorCode::UnauthorizedAccess
+    )]
+    pub market: Account<'info, Market>,
+
+    #[account(mut, address = market.quote_vault)]
+    pub quote_vault: Account<'info, TokenAccount>,
+
+    // Any quote token account the authority wants the fees sent to
+    #[account(mut, token::mint = market.quote_mint)]
+    pub treasury: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> CollectFees<'info> {
+    // Sweeps every fee accrued so far from the quote vault to the treasury
+    pub fn collect_fees(&mut self) -> Result<()> {
+        let fees_accrued = self.market.fees_accrued;
+        require!(fees_accrued > 0, ErrorCode::NoFeesToCollect);
+
+        let seeds = &[
+            b"market",
+            self.market.base_mint.as_ref(),
+            self.market.quote_mint.as_ref(),
+            &[self.market.bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        let cpi_accounts = Transfer {
+            authority: self.market.to_account_info(),
+            from: self.quote_vault.to_account_info(),
+            to: self.treasury.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            cpi_accounts,
+            signer_seeds,
+        );
+        transfer(cpi_ctx, fees_accrued)?;
+
+        self.market.fees_accrued = 0;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,131 @@
+use anchor_lang::prelude::*;
+
+use crate::{error::ErrorCode, *};
+
+#[derive(Accounts)]
+pub struct ConsumeEvents<'info> {
+    #[account(
+        seeds = [b"market", market.base_mint.key().as_ref(), market.quote_mint.key().as_ref()],
+        bump
+    )]
+    pub market: Account<'info, Market>,
+
+    #[account(mut, address = market.event_queue)]
+    pub event_queue: AccountLoader<'info, EventQueue>,
+}
+
+impl<'info> ConsumeEvents<'info> {
+    // Permissionless crank: applies queued fill and out events to the makers'
+    // OpenOrders accounts passed in remaining_accounts, oldest event first
+    pub fn consume_events(
+        &mut self,
+        limit: u16,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<()> {
+        // Every account must be a distinct, canonical OpenOrders PDA of this
+        // market, so a user's balances elsewhere can never be credited
+        let mut maker_accounts: Vec<(Pubkey, &AccountInfo<'info>)> =
+            Vec::with_capacity(remaining_accounts.len());
+        for account in remaining_accounts.iter() {
+            let open_orders = Account::<OpenOrders>::try_from(account)?;
+            require_keys_eq!(
+                open_orders.market,
+                self.market.key(),
+                ErrorCode::InvalidOpenOrders
+            );
+
+            let expected_address = Pubkey::create_program_address(
+                &[
+                    b"user_open_orders",
+                    self.market.key().as_ref(),
+                    open_orders.owner.as_ref(),
+                    &[open_orders.bump],
+                ],
+                &crate::ID,
+            )
+            .map_err(|_| ErrorCode::InvalidOpenOrders)?;
+            require_keys_eq!(
+                account.key(),
+                expected_address,
+                ErrorCode::InvalidOpenOrders
+            );
+
+            require!(
+                !maker_accounts
+                    .iter()
+                    .any(|(owner, _)| *owner == open_orders.owner),
+                ErrorCode::DuplicateOpenOrders
+            );
+            require!(account.is_writable, ErrorCode::InsufficientFunds);
+
+            maker_accounts.push((open_orders.owner, account));
+        }
+
+        let mut event_queue = self.event_queue.load_mut()?;
+
+        for _ in 0..limit {
+            let Some(event) = event_queue.peek_front().copied() else {
+                break;
+            };
+
+            // Events must be applied in order, so stop at the first maker whose
+            // account wasn't provided and leave the rest for a later crank
+            let Some(&(_, maker_account)) = maker_accounts
+                .iter()
+                .find(|(owner, _)| *owner == event.maker)
+            else {
+                break;
+            };
+
+            let mut maker_open_orders: Account<OpenOrders> = Account::try_from(maker_account)?;
+
+            if event.event_type == EventType::Fill as u8 {
+                let base_amount = self.market.base_amount(event.size)?;
+                let quote_amount = self.market.quote_amount(event.price, event.size)?;
+                let maker_fee = event.maker_fee.max(0) as u64;
+                let maker_rebate = event.maker_fee.min(0).unsigned_abs();
+                if event.is_bid() {
+                    // Maker (buyer) receives base for the quote it had locked,
+                    // which also covers its fee
+                    maker_open_orders.quote_locked = quote_amount
+                        .checked_add(maker_fee)
+                        .and_then(|spent| maker_open_orders.quote_locked.checked_sub(spent))
+                        .ok_or(ErrorCode::InsufficientFunds)?;
+                    maker_open_orders.quote_free = maker_open_orders
+                        .quote_free
+                        .checked_add(maker_rebate)
+                        .ok_or(ErrorCode::MathOverflow)?;
+                    maker_open_orders.base_free = maker_open_orders
+                        .base_free
+                        .checked_add(base_amount)
+                        .ok_or(ErrorCode::MathOverflow)?;
+                } else {
+                    // Maker (seller) receives quote for the base it had locked,
+                    // less its fee or plus its rebate
+                    maker_open_orders.base_locked = maker_open_orders
+                        .base_locked
+                        .checked_sub(base_amount)
+                        .ok_or(ErrorCode::InsufficientFunds)?;
+                    maker_open_orders.quote_free = quote_amount
+                        .checked_add(maker_rebate)
+                        .and_then(|proceeds| proceeds.checked_sub(maker_fee))
+                        .and_then(|proceeds| maker_open_orders.quote_free.checked_add(proceeds))
+                        .ok_or(ErrorCode::MathOverflow)?;
+                }
+            } else {
+                let locked_amount = self.market.out_event_locked_amount(&event)?;
+                maker_open_orders.release_locked(event.is_bid(), locked_amount)?;
+            }
+            if event.maker_out != 0 {
+                maker_open_orders.remove_order(event.order_id);
+            }
+
+            // Account::try_from only borrowed the data while deserializing
+            maker_open_orders.try_serialize(&mut *maker_account.data.borrow_mut())?;
+
+            event_queue.pop_front();
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,99 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token::{transfer, Token, TokenAccount, Transfer},
+};
+
+use crate::{error::ErrorCode, *};
+
+#[derive(Accounts)]
+pub struct Deposit<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(
+        seeds = [b"market", market.base_mint.key().as_ref(), market.quote_mint.key().as_ref()],
+        bump
+    )]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        init_if_needed,
+        payer = signer,
+        space = 8 + OpenOrders::INIT_SPACE,
+        seeds = [b"user_open_orders", market.key().as_ref(), signer.key().as_ref()],
+        bump
+    )]
+    pub open_orders: Box<Account<'info, OpenOrders>>,
+
+    #[account(
+        mut,
+        associated_token::mint = market.base_mint,
+        associated_token::authority = market,
+        associated_token::token_program = token_program
+    )]
+    pub base_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        associated_token::mint = market.quote_mint,
+        associated_token::authority = market,
+        associated_token::token_program = token_program
+    )]
+    pub quote_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        associated_token::mint = market.base_mint,
+        associated_token::authority = signer,
+        associated_token::token_program = token_program
+    )]
+    pub user_base_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        associated_token::mint = market.quote_mint,
+        associated_token::authority = signer,
+        associated_token::token_program = token_program
+    )]
+    pub user_quote_vault: Account<'info, TokenAccount>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+impl<'info> Deposit<'info> {
+    // Moves tokens into the market's vault and credits them to the signer's
+    // free balance, where later orders are paid from before any transfer
+    pub fn deposit(&mut self, is_base: bool, amount: u64, bumps: &DepositBumps) -> Result<()> {
+        require!(amount > 0, ErrorCode::InvalidDepositAmount);
+        self.open_orders
+            .init_if_new(self.market.key(), self.signer.key(), bumps.open_orders);
+
+        let cpi_accounts = if is_base {
+            Transfer {
+                authority: self.signer.to_account_info(),
+                from: self.user_base_vault.to_account_info(),
+                to: self.base_vault.to_account_info(),
+            }
+        } else {
+            Transfer {
+                authority: self.signer.to_account_info(),
+                from: self.user_quote_vault.to_account_info(),
+                to: self.quote_vault.to_account_info(),
+            }
+        };
+        let cpi_ctx = CpiContext::new(self.token_program.to_account_info(), cpi_accounts);
+        transfer(cpi_ctx, amount)?;
+
+        let free = if is_base {
+            &mut self.open_orders.base_free
+        } else {
+            &mut self.open_orders.quote_free
+        };
+        *free = free.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+
+        Ok(())
+    }
+}
@@ -6,11 +6,8 @@ use anchor_spl::{
     token::{Mint, Token, TokenAccount},
 };
 
-use crate::*;
+use crate::{events::*, *};
 
-#[allow(overflowing_literals)]
-pub const MAX_ORDERS: usize = 128;
-pub const ORDER_BOOK_SPACE: usize = 8 + 32 + 1 + 4 + (MAX_ORDERS * 72);
 #[derive(Accounts)]
 pub struct Initialize<'info> {
     #[account(mut)]
@@ -28,23 +25,16 @@ pub struct Initialize<'info> {
     )]
     pub market: Account<'info, Market>, // sol-usdc
 
-    #[account(
-        init,
-        payer = signer,
-        space = ORDER_BOOK_SPACE,
-        seeds = [b"bids", market.key().as_ref()],
-        bump
-    )]
-    pub bids: Account<'info, OrderBook>,
+    // Allocated by the client with ORDER_BOOK_SPACE bytes, owned by this program
+    #[account(zero)]
+    pub bids: AccountLoader<'info, OrderBook>,
 
-    #[account(
-        init,
-        payer = signer,
-        space = ORDER_BOOK_SPACE,
-        seeds = [b"asks", market.key().as_ref()],
-        bump
-    )]
-    pub asks: Account<'info, OrderBook>,
+    #[account(zero)]
+    pub asks: AccountLoader<'info, OrderBook>,
+
+    // Allocated by the client with EVENT_QUEUE_SPACE bytes, owned by this program
+    #[account(zero)]
+    pub event_queue: AccountLoader<'info, EventQueue>,
 
     #[account(
         init,
@@ -69,35 +59,80 @@ pub struct Initialize<'info> {
     pub associated_token_program: Program<'info, AssociatedToken>,
 }
 
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct MarketParams {
+    pub book_full_policy: OrderBookFullPolicy,
+    pub tick_size: u64,
+    pub base_lot_size: u64,
+    pub quote_lot_size: u64,
+    pub min_base_order_size: u64,
+    pub max_orders_per_user: u8,
+    pub maker_fee_bps: i16,
+    pub taker_fee_bps: u16,
+    pub referrer_share_bps: u16,
+}
+
 impl<'info> Initialize<'info> {
-    pub fn initialize(&mut self, bumps: &InitializeBumps) -> Result<()> {
+    pub fn initialize(&mut self, params: MarketParams, bumps: &InitializeBumps) -> Result<()> {
         // SECTION 1: Initialize the Market Account
         self.market.set_inner(Market {
             authority: self.signer.key(),
+            pending_authority: Pubkey::default(),
             base_mint: self.base_mint.key(),
             quote_mint: self.quote_mint.key(),
             base_vault: self.base_vault.key(),
             quote_vault: self.quote_vault.key(),
             bids: self.bids.key(),
             asks: self.asks.key(),
+            event_queue: self.event_queue.key(),
+            base_decimals: self.base_mint.decimals,
+            quote_decimals: self.quote_mint.decimals,
+            tick_size: params.tick_size,
+            base_lot_size: params.base_lot_size,
+            quote_lot_size: params.quote_lot_size,
+            min_base_order_size: params.min_base_order_size,
+            maker_fee_bps: params.maker_fee_bps,
+            taker_fee_bps: params.taker_fee_bps,
+            referrer_share_bps: params.referrer_share_bps,
+            fees_accrued: 0,
+            max_orders_per_user: params.max_orders_per_user,
+            seq_num: 0,
+            event_seq_num: 0,
+            book_full_policy: params.book_full_policy,
+            status: MarketStatus::Active,
             bump: bumps.market,
         });
+        self.market.validate_config()?;
 
-        // SECTION 2: Initialize the Bids OrderBook
-        self.bids.set_inner(OrderBook {
+        emit!(MarketInitialized {
             market: self.market.key(),
-            is_bid: true,
-            orders: Vec::new(),
-            bump: bumps.bids,
+            seq_num: self.market.next_event_seq_num(),
+            authority: self.market.authority,
+            base_mint: self.market.base_mint,
+            quote_mint: self.market.quote_mint,
+            tick_size: params.tick_size,
+            base_lot_size: params.base_lot_size,
+            quote_lot_size: params.quote_lot_size,
+            min_base_order_size: params.min_base_order_size,
+            max_orders_per_user: params.max_orders_per_user,
+            maker_fee_bps: params.maker_fee_bps,
+            taker_fee_bps: params.taker_fee_bps,
+            referrer_share_bps: params.referrer_share_bps,
+            book_full_policy: params.book_full_policy,
         });
 
+        // SECTION 2: Initialize the Bids OrderBook
+        let mut bids = self.bids.load_init()?;
+        bids.market = self.market.key();
+        bids.is_bid = 1;
+
         // SECTION 3: Initialize the Asks OrderBook
-        self.asks.set_inner(OrderBook {
-            market: self.market.key(),
-            is_bid: false,
-            orders: Vec::new(),
-            bump: bumps.asks,
-        });
+        let mut asks = self.asks.load_init()?;
+        asks.market = self.market.key();
+        asks.is_bid = 0;
+
+        // SECTION 4: Initialize the EventQueue
+        self.event_queue.load_init()?.market = self.market.key();
 
         Ok(())
     }
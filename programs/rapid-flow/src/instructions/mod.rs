@@ -7,5 +7,29 @@ pub use place_order::*;
 pub mod settle_funds;
 pub use settle_funds::*;
 
+pub mod deposit;
+pub use deposit::*;
+
 pub mod cancel_order;
 pub use cancel_order::*;
+
+pub mod consume_events;
+pub use consume_events::*;
+
+pub mod collect_fees;
+pub use collect_fees::*;
+
+pub mod update_market_config;
+pub use update_market_config::*;
+
+pub mod propose_authority;
+pub use propose_authority::*;
+
+pub mod accept_authority;
+pub use accept_authority::*;
+
+pub mod prune_expired_orders;
+pub use prune_expired_orders::*;
+
+pub mod close_open_orders;
+pub use close_open_orders::*;
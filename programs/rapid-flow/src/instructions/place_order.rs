@@ -1,13 +1,11 @@
 #![allow(warnings)]
-use std::env::var;
-
 use anchor_lang::prelude::*;
 use anchor_spl::{
     associated_token::AssociatedToken,
     token::{transfer, Mint, Token, TokenAccount, Transfer},
 };
 
-use crate::{error::ErrorCode, *};
+use crate::{error::ErrorCode, events::*, *};
 
 #[derive(Accounts)]
 pub struct PlaceOrder<'info> {
@@ -21,19 +19,14 @@ pub struct PlaceOrder<'info> {
     )]
     pub market: Account<'info, Market>,
 
-    #[account(
-        mut,
-        seeds = [b"bids", market.key().as_ref()],
-        bump
-    )]
-    pub bids: Account<'info, OrderBook>,
+    #[account(mut, address = market.bids)]
+    pub bids: AccountLoader<'info, OrderBook>,
 
-    #[account(
-        mut,
-        seeds = [b"asks", market.key().as_ref()],
-        bump
-    )]
-    pub asks: Account<'info, OrderBook>,
+    #[account(mut, address = market.asks)]
+    pub asks: AccountLoader<'info, OrderBook>,
+
+    #[account(mut, address = market.event_queue)]
+    pub event_queue: AccountLoader<'info, EventQueue>,
 
     #[account(
         init_if_needed,
@@ -42,7 +35,7 @@ pub struct PlaceOrder<'info> {
         seeds = [b"user_open_orders", market.key().as_ref(), signer.key().as_ref()],
         bump
     )]
-    pub user_open_orders: Account<'info, OpenOrders>,
+    pub user_open_orders: Box<Account<'info, OpenOrders>>,
 
     #[account(
         mut,
@@ -76,263 +69,687 @@ pub struct PlaceOrder<'info> {
     )]
     pub user_quote_vault: Account<'info, TokenAccount>,
 
+    // Optional quote token account paid a share of the taker fees. Paying the
+    // quote vault would move nothing, yet the fees would leave fees_accrued.
+    #[account(
+        mut,
+        token::mint = market.quote_mint,
+        constraint = referrer.key() != quote_vault.key() @ ErrorCode::InvalidReferrer
+    )]
+    pub referrer: Option<Account<'info, TokenAccount>>,
+
     pub system_program: Program<'info, System>,
     pub token_program: Program<'info, Token>,
     pub associated_token_program: Program<'info, AssociatedToken>,
 }
 
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct PlaceOrderArgs {
+    pub is_bid: bool,
+    pub price: u64, // PRICE_DECIMALS fixed-point quote per base
+    pub size: u64,  // base lots
+    pub order_type: OrderType,
+    pub self_trade_behavior: SelfTradeBehavior,
+    pub client_order_id: u64,
+    pub expiry_timestamp: i64, // zero for good-till-cancelled
+}
+
+// What a taker order did against the book
+#[derive(Default)]
+struct MatchResult {
+    filled_size: u64,     // base lots traded
+    filled_quote: u64,    // quote tokens they traded for
+    taker_fees: u64,      // quote tokens charged on top
+    self_trade_size: u64, // base lots dropped against the signer's own orders
+}
+
 impl<'info> PlaceOrder<'info> {
-    pub fn place_order(
+    pub fn place_order(&mut self, args: PlaceOrderArgs, bumps: &PlaceOrderBumps) -> Result<()> {
+        self.init_open_orders(bumps);
+        self.new_order(args)
+    }
+
+    // Replaces one of the signer's resting orders. Only reducing its size
+    // keeps the order's place in the queue; any other change cancels it and
+    // places `args` as a new order, which the funds the old order had locked
+    // go towards.
+    pub fn edit_order(
         &mut self,
-        is_bid: bool,
-        price: u64,
-        mut size: u64,
-        remaining_accounts: &'info [AccountInfo<'info>],
+        order_id: u128,
+        args: PlaceOrderArgs,
+        bumps: &PlaceOrderBumps,
     ) -> Result<()> {
-        let clock = Clock::get()?;
+        require!(
+            self.market.status != MarketStatus::Paused,
+            ErrorCode::MarketPaused
+        );
+        self.init_open_orders(bumps);
+        let is_bid = args.is_bid;
+
+        let mut order_book = if is_bid {
+            self.bids.load_mut()?
+        } else {
+            self.asks.load_mut()?
+        };
+        let order = *order_book
+            .find_order(order_id)
+            .filter(|o| o.owner == self.signer.key())
+            .ok_or(ErrorCode::OrderNotFound)?;
+
+        let size_reduced_only = self.market.price_in_lots(args.price)? == order.price
+            && args.size <= order.size
+            && !matches!(
+                args.order_type,
+                OrderType::ImmediateOrCancel | OrderType::FillOrKill
+            );
+        if size_reduced_only {
+            require!(
+                args.size >= self.market.min_base_order_size,
+                ErrorCode::OrderTooSmall
+            );
+            require!(
+                args.expiry_timestamp == 0 || args.expiry_timestamp > Clock::get()?.unix_timestamp,
+                ErrorCode::InvalidExpiry
+            );
+            let reduced_size = order.size - args.size;
+            order_book.reduce_order(order_id, reduced_size)?;
+            let resting_order = order_book
+                .find_order_mut(order_id)
+                .ok_or(ErrorCode::OrderNotFound)?;
+            resting_order.client_order_id = args.client_order_id;
+            resting_order.expiry_timestamp = args.expiry_timestamp;
+            drop(order_book);
+            if let Some(slot) = self.user_open_orders.find_order_mut(order_id) {
+                slot.client_order_id = args.client_order_id;
+            }
+            emit_order_cancelled(&mut self.market, &order, reduced_size);
 
-        if self.user_open_orders.owner == Pubkey::default() {
-            self.user_open_orders.owner = self.signer.key();
-            self.user_open_orders.market = self.market.key();
-            self.user_open_orders.base_free = 0;
-            self.user_open_orders.base_locked = 0;
-            self.user_open_orders.quote_free = 0;
-            self.user_open_orders.quote_locked = 0;
+            let released_amount = self.market.order_locked_amount(&order, reduced_size)?;
+            return self
+                .user_open_orders
+                .release_locked(is_bid, released_amount);
         }
 
-        // Store original size for token transfer
-        let original_size = size;
+        order_book.remove_order(order_id);
+        drop(order_book);
+        self.user_open_orders.remove_order(order_id);
+        emit_order_cancelled(&mut self.market, &order, order.size);
+
+        let released_amount = self.market.order_locked_amount(&order, order.size)?;
+        self.user_open_orders
+            .release_locked(is_bid, released_amount)?;
+        self.new_order(args)
+    }
+
+    // Matches and rests an order, paying for it out of the user's free
+    // balance first and transferring only the shortfall
+    fn new_order(&mut self, args: PlaceOrderArgs) -> Result<()> {
+        let PlaceOrderArgs {
+            is_bid,
+            price,
+            size,
+            order_type,
+            self_trade_behavior,
+            client_order_id,
+            expiry_timestamp,
+        } = args;
+        let clock = Clock::get()?;
+        let fees_accrued_before = self.market.fees_accrued;
+
+        require!(
+            self.market.status == MarketStatus::Active,
+            ErrorCode::MarketNotActive
+        );
+        require!(
+            expiry_timestamp == 0 || expiry_timestamp > clock.unix_timestamp,
+            ErrorCode::InvalidExpiry
+        );
+
+        let mut price = self.market.price_in_lots(price)?;
+        require!(
+            price > 0 && price % self.market.tick_size == 0,
+            ErrorCode::InvalidPrice
+        );
+        require!(
+            size >= self.market.min_base_order_size,
+            ErrorCode::OrderTooSmall
+        );
+
+        let MatchResult {
+            filled_size,
+            filled_quote,
+            taker_fees,
+            self_trade_size,
+        } = match order_type {
+            OrderType::PostOnly | OrderType::PostOnlySlide => {
+                price = self.post_only_price(is_bid, price, order_type, clock.unix_timestamp)?;
+                MatchResult::default()
+            }
+            _ => self.match_order(
+                is_bid,
+                price,
+                size,
+                u64::MAX,
+                self_trade_behavior,
+                clock.unix_timestamp,
+            )?,
+        };
+        let remaining_size = size
+            .checked_sub(filled_size)
+            .and_then(|size| size.checked_sub(self_trade_size))
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        if order_type == OrderType::FillOrKill {
+            require!(filled_size == size, ErrorCode::FillOrKillNotFilled);
+        }
 
+        // Immediate orders never rest, so their unfilled portion is never taken
+        let rest_size = match order_type {
+            OrderType::ImmediateOrCancel | OrderType::FillOrKill => 0,
+            _ => remaining_size,
+        };
+
+        // ✅ TRANSFER TOKENS for the filled and resting size (after matching),
+        // plus a bid's taker fees and the maker fee reserve for what rests
+        let transfer_amount = if is_bid {
+            let rest_locked_amount =
+                self.market
+                    .bid_locked_amount(self.market.maker_fee_bps, price, rest_size)?;
+            self.market
+                .quote_amount(price, filled_size)?
+                .checked_add(taker_fees)
+                .and_then(|amount| amount.checked_add(rest_locked_amount))
+                .ok_or(ErrorCode::MathOverflow)?
+        } else {
+            let base_lots = filled_size
+                .checked_add(rest_size)
+                .ok_or(ErrorCode::MathOverflow)?;
+            self.market.base_amount(base_lots)?
+        };
+        self.fund_order(is_bid, transfer_amount)?;
+        self.pay_referrer(taker_fees, fees_accrued_before)?;
+
+        // A bid pays its limit price for the filled size; credit back the
+        // price improvement from filling at lower ask prices
         if is_bid {
-            // ✅ TRANSFER QUOTE TOKENS FIRST (before matching)
-            let quote_amount = price.checked_mul(original_size).ok_or(ErrorCode::MathOverflow)?;
-            
-            let cpi_accounts = Transfer {
-                authority: self.signer.to_account_info(),
-                from: self.user_quote_vault.to_account_info(),
-                to: self.quote_vault.to_account_info(),
-            };
-            let cpi_ctx = CpiContext::new(self.token_program.to_account_info(), cpi_accounts);
-            transfer(cpi_ctx, quote_amount)?;
+            let price_improvement = self
+                .market
+                .quote_amount(price, filled_size)?
+                .checked_sub(filled_quote)
+                .ok_or(ErrorCode::MathOverflow)?;
+            self.user_open_orders.quote_free = self
+                .user_open_orders
+                .quote_free
+                .checked_add(price_improvement)
+                .ok_or(ErrorCode::MathOverflow)?;
+        }
 
-            // Buy order: match against asks (sellers)
-            let asks = &mut self.asks;
-            let mut i = 0;
+        // Lock ONLY remaining unfilled size
+        if rest_size > 0 {
+            self.rest_order(
+                is_bid,
+                price,
+                rest_size,
+                client_order_id,
+                expiry_timestamp,
+                clock.unix_timestamp,
+            )?;
+        }
 
-            while i < asks.orders.len() && size > 0 {
-                let ask_order = &mut asks.orders[i];
+        Ok(())
+    }
 
-                // Only match if price is acceptable
-                if price < ask_order.price {
-                    i += 1;
-                    continue;
-                }
+    // A market buy spends up to `amount` quote tokens including fees and a
+    // market sell sells up to `amount` base lots, never trading beyond
+    // `limit_price`. Only what actually fills is paid for, out of the user's
+    // free balance first.
+    pub fn place_market_order(
+        &mut self,
+        is_bid: bool,
+        amount: u64,
+        limit_price: u64,
+        self_trade_behavior: SelfTradeBehavior,
+        bumps: &PlaceOrderBumps,
+    ) -> Result<()> {
+        require!(
+            self.market.status == MarketStatus::Active,
+            ErrorCode::MarketNotActive
+        );
+        let clock = Clock::get()?;
+        self.init_open_orders(bumps);
+        let fees_accrued_before = self.market.fees_accrued;
+
+        if !is_bid {
+            require!(
+                amount >= self.market.min_base_order_size,
+                ErrorCode::OrderTooSmall
+            );
+        }
 
-                // Find the matching counter-party account from remaining_accounts
-                let mut counter_user_account_opt = None;
-                for account in remaining_accounts.iter() {
-                    if let Ok(counter_open_orders) = Account::<OpenOrders>::try_from(account) {
-                        if counter_open_orders.owner == ask_order.owner {
-                            counter_user_account_opt = Some(account);
-                            break;
-                        }
-                    }
-                }
+        let (max_size, max_quote) = if is_bid {
+            (u64::MAX, amount)
+        } else {
+            (amount, u64::MAX)
+        };
+        let limit_price = self.market.price_in_lots(limit_price)?;
+        let MatchResult {
+            filled_size,
+            filled_quote,
+            taker_fees,
+            ..
+        } = self.match_order(
+            is_bid,
+            limit_price,
+            max_size,
+            max_quote,
+            self_trade_behavior,
+            clock.unix_timestamp,
+        )?;
+
+        let transfer_amount = if is_bid {
+            filled_quote
+                .checked_add(taker_fees)
+                .ok_or(ErrorCode::MathOverflow)?
+        } else {
+            self.market.base_amount(filled_size)?
+        };
+        self.fund_order(is_bid, transfer_amount)?;
+        self.pay_referrer(taker_fees, fees_accrued_before)
+    }
 
-                // If we found the matching account, process the match
-                if let Some(counter_user_account) = counter_user_account_opt {
-                    require!(
-                        counter_user_account.is_writable,
-                        ErrorCode::InsufficientFunds
-                    );
-
-                    let mut counter_user_open_orders: Account<OpenOrders> =
-                        Account::try_from(counter_user_account)?;
-
-                    // Calculate match size (take minimum of what's available)
-                    let match_size = core::cmp::min(size, ask_order.size);
-                    let match_quote_amount = ask_order
-                        .price
-                        .checked_mul(match_size)
-                        .ok_or(ErrorCode::MathOverflow)?;
-
-                    // Update taker (buyer) balances - they get base tokens
-                    self.user_open_orders.base_free = self
-                        .user_open_orders
-                        .base_free
-                        .checked_add(match_size)
-                        .ok_or(ErrorCode::MathOverflow)?;
-
-                    // Update maker (seller) balances
-                    counter_user_open_orders.base_locked = counter_user_open_orders
-                        .base_locked
-                        .checked_sub(match_size)
-                        .ok_or(ErrorCode::InsufficientFunds)?;
-                    counter_user_open_orders.quote_free = counter_user_open_orders
-                        .quote_free
-                        .checked_add(match_quote_amount)
-                        .ok_or(ErrorCode::MathOverflow)?;
-
-                    // Extract struct clone (to release RefCell borrow)
-                    let counter_user_data = (*counter_user_open_orders).clone();
-                    drop(counter_user_open_orders);
-
-                    // Re-borrow and serialize
-                    counter_user_data
-                        .try_serialize(&mut *counter_user_account.data.borrow_mut())?;
-
-                    // Update order size and remove if fully filled
-                    ask_order.size = ask_order
-                        .size
-                        .checked_sub(match_size)
-                        .ok_or(ErrorCode::MathOverflow)?;
-                    size = size
-                        .checked_sub(match_size)
-                        .ok_or(ErrorCode::MathOverflow)?;
-
-                    if ask_order.size == 0 {
-                        asks.orders.remove(i);
-                    } else {
-                        i += 1;
-                    }
-                } else {
-                    // No matching account found, skip this order
-                    i += 1;
-                }
-            }
+    fn init_open_orders(&mut self, bumps: &PlaceOrderBumps) {
+        self.user_open_orders.init_if_new(
+            self.market.key(),
+            self.signer.key(),
+            bumps.user_open_orders,
+        );
+    }
 
-            // Lock ONLY remaining unfilled size
-            if size > 0 {
-                let unfilled_quote_amount = price.checked_mul(size).ok_or(ErrorCode::MathOverflow)?;
-                
-                self.user_open_orders.quote_locked = self
-                    .user_open_orders
-                    .quote_locked
-                    .checked_add(unfilled_quote_amount)
-                    .ok_or(ErrorCode::MathOverflow)?;
+    // Covers `amount` out of the free balance and transfers whatever is left
+    // from the user's token account
+    fn fund_order(&mut self, is_bid: bool, amount: u64) -> Result<()> {
+        let from_free = self.user_open_orders.take_free(is_bid, amount);
+        self.transfer_from_user(is_bid, amount - from_free)
+    }
+
+    // Bids pay in quote tokens, asks in base tokens
+    fn transfer_from_user(&self, is_bid: bool, amount: u64) -> Result<()> {
+        if amount == 0 {
+            return Ok(());
+        }
 
-                self.bids.orders.push(Order {
-                    order_id: clock.unix_timestamp as u128,
-                    owner: self.signer.key(),
-                    price,
-                    size,
-                    timestamp: clock.unix_timestamp,
-                });
+        let cpi_accounts = if is_bid {
+            Transfer {
+                authority: self.signer.to_account_info(),
+                from: self.user_quote_vault.to_account_info(),
+                to: self.quote_vault.to_account_info(),
             }
         } else {
-            // ✅ TRANSFER BASE TOKENS FIRST (before matching)
-            let cpi_accounts = Transfer {
+            Transfer {
                 authority: self.signer.to_account_info(),
                 from: self.user_base_vault.to_account_info(),
                 to: self.base_vault.to_account_info(),
-            };
-            let cpi_ctx = CpiContext::new(self.token_program.to_account_info(), cpi_accounts);
-            transfer(cpi_ctx, original_size)?;
+            }
+        };
+        let cpi_ctx = CpiContext::new(self.token_program.to_account_info(), cpi_accounts);
+        transfer(cpi_ctx, amount)
+    }
 
-            // Sell order: match against bids (buyers)
-            let bids = &mut self.bids;
-            let mut i = 0;
+    // Pays the referrer, if one was passed, its share of the order's taker
+    // fees out of what the order accrued to the market
+    fn pay_referrer(&mut self, taker_fees: u64, fees_accrued_before: u64) -> Result<()> {
+        let Some(referrer) = &self.referrer else {
+            return Ok(());
+        };
+
+        let order_fees = self
+            .market
+            .fees_accrued
+            .checked_sub(fees_accrued_before)
+            .ok_or(ErrorCode::MathOverflow)?;
+        let amount = core::cmp::min(self.market.referrer_fee(taker_fees), order_fees);
+        if amount == 0 {
+            return Ok(());
+        }
 
-            while i < bids.orders.len() && size > 0 {
-                let bid_order = &mut bids.orders[i];
+        let seeds = &[
+            b"market",
+            self.market.base_mint.as_ref(),
+            self.market.quote_mint.as_ref(),
+            &[self.market.bump],
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        let cpi_accounts = Transfer {
+            authority: self.market.to_account_info(),
+            from: self.quote_vault.to_account_info(),
+            to: referrer.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            cpi_accounts,
+            signer_seeds,
+        );
+        transfer(cpi_ctx, amount)?;
+
+        self.market.fees_accrued -= amount;
+
+        emit!(ReferrerFeePaid {
+            market: self.market.key(),
+            seq_num: self.market.next_event_seq_num(),
+            referrer: referrer.key(),
+            taker: self.signer.key(),
+            amount,
+        });
 
-                // Only match if price is acceptable
-                if price > bid_order.price {
-                    i += 1;
-                    continue;
-                }
+        Ok(())
+    }
+
+    // Post-only orders never take liquidity: a crossing PostOnly order fails,
+    // while PostOnlySlide is repriced one tick behind the best opposing order.
+    // Expired orders are skipped, since matching would skip them too.
+    fn post_only_price(
+        &self,
+        is_bid: bool,
+        price: u64,
+        order_type: OrderType,
+        timestamp: i64,
+    ) -> Result<u64> {
+        let order_book = if is_bid {
+            self.asks.load()?
+        } else {
+            self.bids.load()?
+        };
+
+        let Some(best_order) = order_book.iter().find(|o| !o.is_expired(timestamp)) else {
+            return Ok(price);
+        };
+
+        let crosses = if is_bid {
+            price >= best_order.price
+        } else {
+            price <= best_order.price
+        };
+        if !crosses {
+            return Ok(price);
+        }
+
+        require!(
+            order_type == OrderType::PostOnlySlide,
+            ErrorCode::PostOnlyWouldCross
+        );
+        let slid_price = if is_bid {
+            best_order.price.checked_sub(self.market.tick_size)
+        } else {
+            best_order.price.checked_add(self.market.tick_size)
+        };
+        slid_price
+            .filter(|slid_price| *slid_price > 0)
+            .ok_or(ErrorCode::PostOnlyWouldCross.into())
+    }
+
+    // Fills against the opposite book best price first, up to `max_size` base
+    // lots and, for bids, `max_quote` quote tokens including fees. The taker is
+    // credited immediately and the makers' side is queued for
+    // `consume_events`. Each fill accrues its taker fee and maker fee (less
+    // any rebate) to the market. Crossing one of the signer's own resting
+    // orders is handled as `self_trade_behavior` says.
+    fn match_order(
+        &mut self,
+        is_bid: bool,
+        price: u64,
+        max_size: u64,
+        max_quote: u64,
+        self_trade_behavior: SelfTradeBehavior,
+        timestamp: i64,
+    ) -> Result<MatchResult> {
+        let mut order_book = if is_bid {
+            self.asks.load_mut()?
+        } else {
+            self.bids.load_mut()?
+        };
+        let mut event_queue = self.event_queue.load_mut()?;
+
+        let mut filled_size: u64 = 0;
+        let mut filled_quote: u64 = 0;
+        let mut taker_fees: u64 = 0;
+        let mut self_trade_size: u64 = 0;
+
+        while filled_size + self_trade_size < max_size {
+            let Some(maker_order) = order_book.best_order().copied() else {
+                break;
+            };
+
+            // The book is walked best price first, so nothing further can cross
+            let crosses = if is_bid {
+                price >= maker_order.price
+            } else {
+                price <= maker_order.price
+            };
+            if !crosses {
+                break;
+            }
 
-                // Find the matching counter-party account from remaining_accounts
-                let mut counter_user_account_opt = None;
-                for account in remaining_accounts.iter() {
-                    if let Ok(counter_open_orders) = Account::<OpenOrders>::try_from(account) {
-                        if counter_open_orders.owner == bid_order.owner {
-                            counter_user_account_opt = Some(account);
-                            break;
-                        }
+            // Expired orders are dropped as they're reached, and their owners'
+            // funds released through an out event
+            if maker_order.is_expired(timestamp) {
+                order_book.remove_order(maker_order.order_id);
+                event_queue.push_back(Event::new_out(&maker_order, timestamp))?;
+                emit_order_cancelled(&mut self.market, &maker_order, maker_order.size);
+                continue;
+            }
+
+            // The signer's own orders sit on this same OpenOrders account, so
+            // their funds are released here rather than through the queue
+            let is_self_trade = maker_order.owner == self.signer.key();
+            if is_self_trade {
+                match self_trade_behavior {
+                    SelfTradeBehavior::AbortTransaction => {
+                        return err!(ErrorCode::WouldSelfTrade);
                     }
+                    SelfTradeBehavior::CancelProvide => {
+                        order_book.remove_order(maker_order.order_id);
+                        self.user_open_orders.remove_order(maker_order.order_id);
+                        emit_order_cancelled(&mut self.market, &maker_order, maker_order.size);
+                        let locked_amount = self
+                            .market
+                            .order_locked_amount(&maker_order, maker_order.size)?;
+                        self.user_open_orders
+                            .release_locked(!is_bid, locked_amount)?;
+                        continue;
+                    }
+                    SelfTradeBehavior::DecrementTake => {}
                 }
+            }
 
-                // If we found the matching account, process the match
-                if let Some(counter_user_account) = counter_user_account_opt {
-                    require!(
-                        counter_user_account.is_writable,
-                        ErrorCode::InsufficientFunds
-                    );
-
-                    let mut counter_user_open_orders: Account<OpenOrders> =
-                        Account::try_from(counter_user_account)?;
-
-                    // Calculate match size
-                    let match_size = core::cmp::min(size, bid_order.size);
-                    let match_quote_amount = bid_order
-                        .price
-                        .checked_mul(match_size)
-                        .ok_or(ErrorCode::MathOverflow)?;
-
-                    // Update taker (seller) balances - they get quote tokens
-                    self.user_open_orders.quote_free = self
-                        .user_open_orders
-                        .quote_free
-                        .checked_add(match_quote_amount)
-                        .ok_or(ErrorCode::MathOverflow)?;
-
-                    // Update maker (buyer) balances
-                    counter_user_open_orders.quote_locked = counter_user_open_orders
-                        .quote_locked
-                        .checked_sub(match_quote_amount)
-                        .ok_or(ErrorCode::InsufficientFunds)?;
-                    counter_user_open_orders.base_free = counter_user_open_orders
-                        .base_free
-                        .checked_add(match_size)
-                        .ok_or(ErrorCode::MathOverflow)?;
-
-                    // Extract struct clone (to release RefCell borrow)
-                    let counter_user_data = (*counter_user_open_orders).clone();
-                    drop(counter_user_open_orders);
-
-                    // Re-borrow and serialize
-                    counter_user_data
-                        .try_serialize(&mut *counter_user_account.data.borrow_mut())?;
-
-                    // Update order size and remove if fully filled
-                    bid_order.size = bid_order
-                        .size
-                        .checked_sub(match_size)
-                        .ok_or(ErrorCode::MathOverflow)?;
-                    size = size
-                        .checked_sub(match_size)
-                        .ok_or(ErrorCode::MathOverflow)?;
-
-                    if bid_order.size == 0 {
-                        bids.orders.remove(i);
-                    } else {
-                        i += 1;
-                    }
-                } else {
-                    // No matching account found, skip this order
-                    i += 1;
+            // Calculate match size (take minimum of what's available)
+            let mut match_size =
+                core::cmp::min(max_size - filled_size - self_trade_size, maker_order.size);
+            if is_bid {
+                // Rounding the fee up per lot never underestimates the cost
+                let lot_quote_amount = self.market.quote_amount(maker_order.price, 1)?;
+                let lot_cost = lot_quote_amount
+                    .checked_add(self.market.taker_fee(lot_quote_amount)?)
+                    .ok_or(ErrorCode::MathOverflow)?;
+                let affordable_size = max_quote
+                    .saturating_sub(filled_quote.saturating_add(taker_fees))
+                    .checked_div(lot_cost)
+                    .unwrap_or(u64::MAX);
+                match_size = core::cmp::min(match_size, affordable_size);
+            }
+            if match_size == 0 {
+                break;
+            }
+
+            if is_self_trade {
+                order_book.reduce_order(maker_order.order_id, match_size)?;
+                if match_size == maker_order.size {
+                    self.user_open_orders.remove_order(maker_order.order_id);
                 }
+                emit_order_cancelled(&mut self.market, &maker_order, match_size);
+                let locked_amount = self.market.order_locked_amount(&maker_order, match_size)?;
+                self.user_open_orders
+                    .release_locked(!is_bid, locked_amount)?;
+                self_trade_size += match_size;
+                continue;
             }
 
-            // Lock ONLY remaining unfilled size
-            if size > 0 {
-                self.user_open_orders.base_locked = self
+            let match_base_amount = self.market.base_amount(match_size)?;
+            let match_quote_amount = self.market.quote_amount(maker_order.price, match_size)?;
+            let taker_fee = self.market.taker_fee(match_quote_amount)?;
+            // A rebate is paid out of the taker fee, which it can only exceed
+            // if fees were changed after the maker's order was placed
+            let maker_fee = self
+                .market
+                .maker_fee(maker_order.maker_fee_bps, maker_order.price, match_size)?
+                .max(-(taker_fee as i64));
+            self.market.fees_accrued = (taker_fee as i64)
+                .checked_add(maker_fee)
+                .and_then(|net_fee| self.market.fees_accrued.checked_add_signed(net_fee))
+                .ok_or(ErrorCode::MathOverflow)?;
+
+            if is_bid {
+                // Update taker (buyer) balances - they get base tokens
+                self.user_open_orders.base_free = self
                     .user_open_orders
-                    .base_locked
-                    .checked_add(size)
+                    .base_free
+                    .checked_add(match_base_amount)
+                    .ok_or(ErrorCode::MathOverflow)?;
+            } else {
+                // Update taker (seller) balances - they get quote tokens, less fees
+                self.user_open_orders.quote_free = match_quote_amount
+                    .checked_sub(taker_fee)
+                    .and_then(|proceeds| self.user_open_orders.quote_free.checked_add(proceeds))
                     .ok_or(ErrorCode::MathOverflow)?;
-
-                self.asks.orders.push(Order {
-                    order_id: clock.unix_timestamp as u128,
-                    owner: self.signer.key(),
-                    price,
-                    size,
-                    timestamp: clock.unix_timestamp,
-                });
             }
+
+            // Maker balances are settled when the fill event is consumed
+            event_queue.push_back(Event::new_fill(
+                &maker_order,
+                self.signer.key(),
+                match_size,
+                maker_fee,
+                timestamp,
+            ))?;
+            emit!(OrderFilled {
+                market: self.market.key(),
+                seq_num: self.market.next_event_seq_num(),
+                maker: maker_order.owner,
+                taker: self.signer.key(),
+                maker_order_id: maker_order.order_id,
+                taker_is_bid: is_bid,
+                price: maker_order.price,
+                size: match_size,
+                taker_fee,
+                maker_fee,
+            });
+
+            // Update order size and remove if fully filled
+            order_book.reduce_order(maker_order.order_id, match_size)?;
+
+            filled_size = filled_size
+                .checked_add(match_size)
+                .ok_or(ErrorCode::MathOverflow)?;
+            filled_quote = filled_quote
+                .checked_add(match_quote_amount)
+                .ok_or(ErrorCode::MathOverflow)?;
+            taker_fees = taker_fees
+                .checked_add(taker_fee)
+                .ok_or(ErrorCode::MathOverflow)?;
+        }
+
+        Ok(MatchResult {
+            filled_size,
+            filled_quote,
+            taker_fees,
+            self_trade_size,
+        })
+    }
+
+    fn rest_order(
+        &mut self,
+        is_bid: bool,
+        price: u64,
+        size: u64,
+        client_order_id: u64,
+        expiry_timestamp: i64,
+        timestamp: i64,
+    ) -> Result<()> {
+        if is_bid {
+            let locked_amount =
+                self.market
+                    .bid_locked_amount(self.market.maker_fee_bps, price, size)?;
+            self.user_open_orders.quote_locked = self
+                .user_open_orders
+                .quote_locked
+                .checked_add(locked_amount)
+                .ok_or(ErrorCode::MathOverflow)?;
+        } else {
+            self.user_open_orders.base_locked = self
+                .user_open_orders
+                .base_locked
+                .checked_add(self.market.base_amount(size)?)
+                .ok_or(ErrorCode::MathOverflow)?;
         }
 
+        let mut order_book = if is_bid {
+            self.bids.load_mut()?
+        } else {
+            self.asks.load_mut()?
+        };
+
+        if order_book.is_full() {
+            let evicted_order = self.evict_worst_order(&mut order_book, price, timestamp)?;
+            emit_order_cancelled(&mut self.market, &evicted_order, evicted_order.size);
+        }
+
+        let seq_num = self.market.next_seq_num()?;
+        let order_id = Order::new_order_id(is_bid, price, seq_num);
+        self.user_open_orders.add_order(
+            order_id,
+            is_bid,
+            client_order_id,
+            self.market.max_orders_per_user,
+        )?;
+        order_book.insert_order(Order {
+            order_id,
+            owner: self.signer.key(),
+            price,
+            size,
+            timestamp,
+            client_order_id,
+            expiry_timestamp,
+            maker_fee_bps: self.market.maker_fee_bps,
+            _padding: [0; 6],
+        })?;
+
+        emit!(OrderPlaced {
+            market: self.market.key(),
+            seq_num: self.market.next_event_seq_num(),
+            owner: self.signer.key(),
+            order_id,
+            client_order_id,
+            is_bid,
+            price,
+            size,
+            expiry_timestamp,
+        });
         Ok(())
     }
-}
\ No newline at end of file
+
+    // Makes room in a full book, if the market allows it, by evicting the
+    // worst-priced resting order; its owner's funds are released when the
+    // out event is consumed
+    fn evict_worst_order(
+        &self,
+        order_book: &mut OrderBook,
+        price: u64,
+        timestamp: i64,
+    ) -> Result<Order> {
+        let worst_order = order_book.evict_worst_order(price, self.market.book_full_policy)?;
+        self.event_queue
+            .load_mut()?
+            .push_back(Event::new_out(&worst_order, timestamp))?;
+        Ok(worst_order)
+    }
+}
@@ -0,0 +1,34 @@
+use anchor_lang::prelude::*;
+
+use crate::{error::ErrorCode, events::*, *};
+
+#[derive(Accounts)]
+pub struct ProposeAuthority<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"market", market.base_mint.key().as_ref(), market.quote_mint.key().as_ref()],
+        bump,
+        has_one = authority @ ErrorCode::UnauthorizedAccess
+    )]
+    pub market: Account<'info, Market>,
+}
+
+impl<'info> ProposeAuthority<'info> {
+    // First step of handing the market over: nothing changes until
+    // `new_authority` accepts, so a mistyped key can just be re-proposed.
+    // Proposing the default key withdraws the proposal.
+    pub fn propose_authority(&mut self, new_authority: Pubkey) -> Result<()> {
+        self.market.pending_authority = new_authority;
+
+        emit!(AuthorityProposed {
+            market: self.market.key(),
+            seq_num: self.market.next_event_seq_num(),
+            authority: self.authority.key(),
+            pending_authority: new_authority,
+        });
+
+        Ok(())
+    }
+}
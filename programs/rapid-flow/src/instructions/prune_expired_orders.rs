@@ -0,0 +1,64 @@
+use anchor_lang::prelude::*;
+
+use crate::{error::ErrorCode, events::*, *};
+
+// Most orders one prune can remove. The program heap is never freed, and each
+// pruned order takes its ID plus a 256 byte OrderCancelled event buffer.
+pub const MAX_PRUNE_ORDERS: usize = 32;
+
+#[derive(Accounts)]
+pub struct PruneExpiredOrders<'info> {
+    #[account(
+        mut,
+        seeds = [b"market", market.base_mint.key().as_ref(), market.quote_mint.key().as_ref()],
+        bump
+    )]
+    pub market: Account<'info, Market>,
+
+    #[account(mut, address = market.bids)]
+    pub bids: AccountLoader<'info, OrderBook>,
+
+    #[account(mut, address = market.asks)]
+    pub asks: AccountLoader<'info, OrderBook>,
+
+    #[account(mut, address = market.event_queue)]
+    pub event_queue: AccountLoader<'info, EventQueue>,
+}
+
+impl<'info> PruneExpiredOrders<'info> {
+    // Permissionless: removes up to `limit` expired orders, at most
+    // MAX_PRUNE_ORDERS, from both books. Their owners' funds are released when
+    // the out events are consumed.
+    pub fn prune_expired_orders(&mut self, limit: u8) -> Result<()> {
+        require!(
+            self.market.status != MarketStatus::Paused,
+            ErrorCode::MarketPaused
+        );
+        let now = Clock::get()?.unix_timestamp;
+        let mut event_queue = self.event_queue.load_mut()?;
+        let mut remaining = (limit as usize).min(MAX_PRUNE_ORDERS);
+
+        for order_book in [&self.bids, &self.asks] {
+            let mut order_book = order_book.load_mut()?;
+            let mut expired_order_ids: Vec<u128> = Vec::with_capacity(remaining);
+            expired_order_ids.extend(
+                order_book
+                    .iter()
+                    .filter(|o| o.is_expired(now))
+                    .take(remaining)
+                    .map(|o| o.order_id),
+            );
+
+            for order_id in expired_order_ids.iter() {
+                let Some(order) = order_book.remove_order(*order_id) else {
+                    continue;
+                };
+                event_queue.push_back(Event::new_out(&order, now))?;
+                emit_order_cancelled(&mut self.market, &order, order.size);
+            }
+            remaining -= expired_order_ids.len();
+        }
+
+        Ok(())
+    }
+}
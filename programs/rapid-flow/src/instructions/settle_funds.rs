@@ -5,7 +5,7 @@ use anchor_spl::{
     token::{transfer, Token, TokenAccount, Transfer},
 };
 
-use crate::{error::ErrorCode, *};
+use crate::{error::ErrorCode, events::*, *};
 
 #[derive(Accounts)]
 pub struct SettleFunds<'info> {
@@ -25,7 +25,7 @@ pub struct SettleFunds<'info> {
         bump,
         constraint = open_orders.owner == signer.key() @ ErrorCode::UnauthorizedAccess
     )]
-    pub open_orders: Account<'info, OpenOrders>,
+    pub open_orders: Box<Account<'info, OpenOrders>>,
 
     #[account(
         mut,
@@ -64,6 +64,7 @@ pub struct SettleFunds<'info> {
 }
 
 impl<'info> SettleFunds<'info> {
+    // An `amount` of u64::MAX settles the whole free balance of that side
     pub fn settle_funds(&mut self, is_base: bool, amount: u64) -> Result<()> {
         let base_free = self.open_orders.base_free;
         let quote_free = self.open_orders.quote_free;
@@ -72,15 +73,47 @@ impl<'info> SettleFunds<'info> {
         require!(base_free > 0 || quote_free > 0, ErrorCode::NoFundsToSettle);
 
         // Determine what to settle and validate amount
-        let (amount_to_settle, is_settling_base) = if is_base {
-            require!(base_free > 0, ErrorCode::InsufficientBalanceClaim);
-            require!(amount <= base_free, ErrorCode::InvalidClaimAmount);
-            (amount, true)
+        let free = if is_base { base_free } else { quote_free };
+        require!(free > 0, ErrorCode::InsufficientBalanceClaim);
+        let amount = if amount == u64::MAX { free } else { amount };
+        require!(amount <= free, ErrorCode::InvalidClaimAmount);
+
+        self.transfer_free(is_base, amount)?;
+        if is_base {
+            self.emit_funds_settled(amount, 0);
         } else {
-            require!(quote_free > 0, ErrorCode::InsufficientBalanceClaim);
-            require!(amount <= quote_free, ErrorCode::InvalidClaimAmount);
-            (amount, false)
-        };
+            self.emit_funds_settled(0, amount);
+        }
+        Ok(())
+    }
+
+    // Settles the full free base and quote balances at once
+    pub fn settle_all(&mut self) -> Result<()> {
+        let base_free = self.open_orders.base_free;
+        let quote_free = self.open_orders.quote_free;
+        require!(base_free > 0 || quote_free > 0, ErrorCode::NoFundsToSettle);
+
+        self.transfer_free(true, base_free)?;
+        self.transfer_free(false, quote_free)?;
+        self.emit_funds_settled(base_free, quote_free);
+        Ok(())
+    }
+
+    fn emit_funds_settled(&mut self, base_amount: u64, quote_amount: u64) {
+        emit!(FundsSettled {
+            market: self.market.key(),
+            seq_num: self.market.next_event_seq_num(),
+            owner: self.signer.key(),
+            base_amount,
+            quote_amount,
+        });
+    }
+
+    // Pays `amount` of the free balance out of the vault to the user
+    fn transfer_free(&mut self, is_base: bool, amount: u64) -> Result<()> {
+        if amount == 0 {
+            return Ok(());
+        }
 
         // Prepare signer seeds
         let seeds = &[
@@ -92,38 +125,33 @@ impl<'info> SettleFunds<'info> {
         let signer_seeds = &[&seeds[..]];
 
         // Execute the appropriate transfer
-        if is_settling_base {
-            let cpi_accounts = Transfer {
+        let cpi_accounts = if is_base {
+            Transfer {
                 authority: self.market.to_account_info(),
                 from: self.base_vault.to_account_info(),
                 to: self.user_base_vault.to_account_info(),
-            };
-            let cpi_ctx = CpiContext::new_with_signer(
-                self.token_program.to_account_info(),
-                cpi_accounts,
-                signer_seeds,
-            );
-            transfer(cpi_ctx, amount_to_settle)?;
-            self.open_orders.base_free = base_free
-                .checked_sub(amount_to_settle)
-                .ok_or(ErrorCode::MathOverflow)?;
+            }
         } else {
-            let cpi_accounts = Transfer {
+            Transfer {
                 authority: self.market.to_account_info(),
                 from: self.quote_vault.to_account_info(),
                 to: self.user_quote_vault.to_account_info(),
-            };
-            let cpi_ctx = CpiContext::new_with_signer(
-                self.token_program.to_account_info(),
-                cpi_accounts,
-                signer_seeds,
-            );
-            transfer(cpi_ctx, amount_to_settle)?;
-            self.open_orders.quote_free = quote_free
-                .checked_sub(amount_to_settle)
-                .ok_or(ErrorCode::MathOverflow)?;
+            }
         };
-        
+        let cpi_ctx = CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            cpi_accounts,
+            signer_seeds,
+        );
+        transfer(cpi_ctx, amount)?;
+
+        let free = if is_base {
+            &mut self.open_orders.base_free
+        } else {
+            &mut self.open_orders.quote_free
+        };
+        *free = free.checked_sub(amount).ok_or(ErrorCode::MathOverflow)?;
+
         Ok(())
     }
 }
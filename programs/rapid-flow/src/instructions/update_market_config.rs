@@ -0,0 +1,59 @@
+use anchor_lang::prelude::*;
+
+use crate::{error::ErrorCode, events::*, *};
+
+#[derive(Accounts)]
+pub struct UpdateMarketConfig<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"market", market.base_mint.key().as_ref(), market.quote_mint.key().as_ref()],
+        bump,
+        has_one = authority @ ErrorCode::UnauthorizedAccess
+    )]
+    pub market: Account<'info, Market>,
+
+    #[account(address = market.bids)]
+    pub bids: AccountLoader<'info, OrderBook>,
+
+    #[account(address = market.asks)]
+    pub asks: AccountLoader<'info, OrderBook>,
+
+    #[account(address = market.event_queue)]
+    pub event_queue: AccountLoader<'info, EventQueue>,
+}
+
+impl<'info> UpdateMarketConfig<'info> {
+    pub fn update_market_config(&mut self, config: MarketConfig) -> Result<()> {
+        let old_config = self.market.config();
+
+        // Resting orders and queued events are sized in lots, so their locked
+        // funds would no longer add up if the lot sizes changed under them.
+        // Their prices would also fall off a new tick grid, which post-only
+        // slides step along.
+        if config.tick_size != old_config.tick_size
+            || config.base_lot_size != old_config.base_lot_size
+            || config.quote_lot_size != old_config.quote_lot_size
+        {
+            require!(
+                self.bids.load()?.is_empty()
+                    && self.asks.load()?.is_empty()
+                    && self.event_queue.load()?.is_empty(),
+                ErrorCode::MarketNotEmpty
+            );
+        }
+
+        self.market.set_config(config);
+        self.market.validate_config()?;
+
+        emit!(MarketConfigUpdated {
+            market: self.market.key(),
+            seq_num: self.market.next_event_seq_num(),
+            old_config,
+            new_config: config,
+        });
+
+        Ok(())
+    }
+}
@@ -1,5 +1,10 @@
+// Anchor's `#[program]` and `declare_id!` expand to cfgs this crate doesn't
+// declare (`anchor-debug`, `custom-heap`, `custom-panic`, `solana`) and to
+// the deprecated `AccountInfo::realloc`, which fail clippy with -D warnings
+#![allow(unexpected_cfgs, deprecated)]
 use anchor_lang::prelude::*;
 pub mod error;
+pub mod events;
 pub mod instructions;
 pub use instructions::*;
 
@@ -12,28 +17,109 @@ pub use state::*;
 pub mod rapid_flow {
     use super::*;
 
-    pub fn initialize(ctx: Context<Initialize>) -> Result<()> {
-        ctx.accounts.initialize(&ctx.bumps)?;
+    pub fn initialize(ctx: Context<Initialize>, params: MarketParams) -> Result<()> {
+        ctx.accounts.initialize(params, &ctx.bumps)?;
         Ok(())
     }
 
-    pub fn place_order<'c: 'info, 'info>(
-        ctx: Context<'_, '_, 'c, 'info, PlaceOrder<'c>>,
+    pub fn place_order(ctx: Context<PlaceOrder>, args: PlaceOrderArgs) -> Result<()> {
+        ctx.accounts.place_order(args, &ctx.bumps)?;
+        Ok(())
+    }
+
+    pub fn edit_order(
+        ctx: Context<PlaceOrder>,
+        order_id: u128,
+        args: PlaceOrderArgs,
+    ) -> Result<()> {
+        ctx.accounts.edit_order(order_id, args, &ctx.bumps)
+    }
+
+    pub fn place_market_order(
+        ctx: Context<PlaceOrder>,
         is_bid: bool,
-        price: u64,
-        size: u64,
+        amount: u64,
+        limit_price: u64,
+        self_trade_behavior: SelfTradeBehavior,
     ) -> Result<()> {
-        ctx.accounts
-            .place_order(is_bid, price, size, ctx.remaining_accounts)?;
-        Ok(())
+        ctx.accounts.place_market_order(
+            is_bid,
+            amount,
+            limit_price,
+            self_trade_behavior,
+            &ctx.bumps,
+        )
+    }
+
+    pub fn consume_events<'c: 'info, 'info>(
+        ctx: Context<'_, '_, 'c, 'info, ConsumeEvents<'c>>,
+        limit: u16,
+    ) -> Result<()> {
+        ctx.accounts.consume_events(limit, ctx.remaining_accounts)
     }
 
-    pub fn settle_funds(ctx: Context<SettleFunds>, is_base:bool, amount: u64) -> Result<()> {
+    pub fn settle_funds(ctx: Context<SettleFunds>, is_base: bool, amount: u64) -> Result<()> {
         ctx.accounts.settle_funds(is_base, amount)?;
         Ok(())
     }
 
+    pub fn settle_all(ctx: Context<SettleFunds>) -> Result<()> {
+        ctx.accounts.settle_all()
+    }
+
+    pub fn deposit(ctx: Context<Deposit>, is_base: bool, amount: u64) -> Result<()> {
+        ctx.accounts.deposit(is_base, amount, &ctx.bumps)
+    }
+
+    // Withdrawing moves free balance back to the user's token account, which
+    // is exactly what settling does
+    pub fn withdraw(ctx: Context<SettleFunds>, is_base: bool, amount: u64) -> Result<()> {
+        ctx.accounts.settle_funds(is_base, amount)
+    }
+
     pub fn cancel_order(ctx: Context<CancelOrder>, order_id: u128, is_bid: bool) -> Result<()> {
         ctx.accounts.cancel_order(order_id, is_bid)
     }
+
+    pub fn cancel_order_by_client_id(
+        ctx: Context<CancelOrder>,
+        client_order_id: u64,
+    ) -> Result<()> {
+        ctx.accounts.cancel_order_by_client_id(client_order_id)
+    }
+
+    pub fn cancel_all_orders(
+        ctx: Context<CancelOrder>,
+        is_bid: Option<bool>,
+        limit: u8,
+    ) -> Result<()> {
+        ctx.accounts.cancel_all_orders(is_bid, limit)
+    }
+
+    pub fn prune_expired_orders(ctx: Context<PruneExpiredOrders>, limit: u8) -> Result<()> {
+        ctx.accounts.prune_expired_orders(limit)
+    }
+
+    pub fn collect_fees(ctx: Context<CollectFees>) -> Result<()> {
+        ctx.accounts.collect_fees()
+    }
+
+    pub fn update_market_config(
+        ctx: Context<UpdateMarketConfig>,
+        config: MarketConfig,
+    ) -> Result<()> {
+        ctx.accounts.update_market_config(config)
+    }
+
+    pub fn propose_authority(ctx: Context<ProposeAuthority>, new_authority: Pubkey) -> Result<()> {
+        ctx.accounts.propose_authority(new_authority)
+    }
+
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        ctx.accounts.accept_authority()
+    }
+
+    pub fn close_open_orders(ctx: Context<CloseOpenOrders>) -> Result<()> {
+        ctx.accounts.close_open_orders()
+    }
 }
@@ -0,0 +1,124 @@
+use anchor_lang::prelude::*;
+
+use crate::{error::ErrorCode, Order};
+
+pub const MAX_EVENTS: usize = 512;
+
+// Like the order books, the event queue is allocated by the client with this
+// much space and claimed by `initialize`
+pub const EVENT_QUEUE_SPACE: usize = 8 + std::mem::size_of::<EventQueue>();
+
+#[repr(u8)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum EventType {
+    // a resting order was matched by a taker
+    Fill,
+    // a resting order left the book without trading
+    Out,
+}
+
+#[account(zero_copy)]
+pub struct EventQueue {
+    // ring buffer of maker-side events waiting to be applied by `consume_events`
+    pub market: Pubkey,
+    pub head: u32,
+    pub count: u32,
+    pub seq_num: u64, // total events ever pushed
+    pub events: [Event; MAX_EVENTS],
+}
+
+#[zero_copy]
+pub struct Event {
+    pub event_type: u8,
+    pub is_bid: u8,         // side of the maker's resting order
+    pub maker_fee_bps: i16, // fee rate the maker's order was placed at
+    pub maker_out: u8,      // the maker's order has left the book
+    pub _padding: [u8; 3],
+    pub timestamp: i64,
+    pub seq_num: u64,
+    pub price: u64,     // quote lots per base lot
+    pub size: u64,      // base lots filled, or still resting for out events
+    pub maker_fee: i64, // quote charged to the maker, negative for a rebate
+    pub order_id: u128,
+    pub maker: Pubkey,
+    pub taker: Pubkey, // default for out events
+}
+
+impl Event {
+    pub fn new_fill(
+        maker_order: &Order,
+        taker: Pubkey,
+        size: u64,
+        maker_fee: i64,
+        timestamp: i64,
+    ) -> Self {
+        Self {
+            event_type: EventType::Fill as u8,
+            is_bid: Order::is_bid_order_id(maker_order.order_id) as u8,
+            maker_fee_bps: maker_order.maker_fee_bps,
+            maker_out: (size == maker_order.size) as u8,
+            _padding: [0; 3],
+            timestamp,
+            seq_num: 0,
+            price: maker_order.price,
+            size,
+            maker_fee,
+            order_id: maker_order.order_id,
+            maker: maker_order.owner,
+            taker,
+        }
+    }
+
+    pub fn new_out(order: &Order, timestamp: i64) -> Self {
+        Self {
+            event_type: EventType::Out as u8,
+            is_bid: Order::is_bid_order_id(order.order_id) as u8,
+            maker_fee_bps: order.maker_fee_bps,
+            maker_out: 1,
+            _padding: [0; 3],
+            timestamp,
+            seq_num: 0,
+            price: order.price,
+            size: order.size,
+            maker_fee: 0,
+            order_id: order.order_id,
+            maker: order.owner,
+            taker: Pubkey::default(),
+        }
+    }
+
+    pub fn is_bid(&self) -> bool {
+        self.is_bid != 0
+    }
+}
+
+impl EventQueue {
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.count as usize == MAX_EVENTS
+    }
+
+    pub fn push_back(&mut self, mut event: Event) -> Result<()> {
+        require!(!self.is_full(), ErrorCode::EventQueueFull);
+        event.seq_num = self.seq_num;
+        let slot = (self.head as usize + self.count as usize) % MAX_EVENTS;
+        self.events[slot] = event;
+        self.count += 1;
+        self.seq_num += 1;
+        Ok(())
+    }
+
+    pub fn peek_front(&self) -> Option<&Event> {
+        (!self.is_empty()).then(|| &self.events[self.head as usize])
+    }
+
+    pub fn pop_front(&mut self) -> Option<Event> {
+        let event = *self.peek_front()?;
+        self.head = ((self.head as usize + 1) % MAX_EVENTS) as u32;
+        self.count -= 1;
+        Some(event)
+    }
+}
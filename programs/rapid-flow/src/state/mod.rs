@@ -1,24 +1,23 @@
 use anchor_lang::prelude::*;
 
-#[account]
-pub struct OrderBook {
-    // book
-    pub market: Pubkey,
-    pub is_bid: bool,
-    pub orders: Vec<Order>,
-    pub bump: u8,
-}
+use crate::error::ErrorCode;
 
-#[account]
-#[derive(InitSpace)]
-pub struct Order {
-    // single order
-    pub order_id: u128,
-    pub owner: Pubkey,
-    pub price: u64,
-    pub size: u64,
-    pub timestamp: i64,
-}
+pub mod event_queue;
+pub use event_queue::*;
+
+pub mod order_book;
+pub use order_book::*;
+
+// Order prices are quoted in quote tokens per base token with this many
+// decimals, e.g. 142.35 is 142_350_000
+pub const PRICE_DECIMALS: u32 = 6;
+
+// Fees are in basis points of the quote traded
+pub const FEE_BPS_DENOMINATOR: u64 = 10_000;
+
+// Most resting orders one user can have on a market; each market sets its
+// own limit up to this
+pub const MAX_OPEN_ORDERS: usize = 32;
 
 #[account]
 #[derive(InitSpace)]
@@ -29,18 +28,428 @@ pub struct OpenOrders {
     pub base_locked: u64,
     pub quote_free: u64,
     pub quote_locked: u64,
+    // The owner's resting orders. A maker's slot is freed once the event
+    // taking its order off the book is consumed.
+    pub orders: [OpenOrderSlot; MAX_OPEN_ORDERS],
     pub bump: u8,
 }
 
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, InitSpace)]
+pub struct OpenOrderSlot {
+    pub order_id: u128, // zero for an empty slot
+    pub is_bid: bool,
+    pub client_order_id: u64,
+}
+
+impl OpenOrders {
+    // Sets up an account just created by `init_if_needed`
+    pub fn init_if_new(&mut self, market: Pubkey, owner: Pubkey, bump: u8) {
+        if self.owner == Pubkey::default() {
+            self.market = market;
+            self.owner = owner;
+            self.base_free = 0;
+            self.base_locked = 0;
+            self.quote_free = 0;
+            self.quote_locked = 0;
+            self.orders = [OpenOrderSlot::default(); MAX_OPEN_ORDERS];
+            self.bump = bump;
+        }
+    }
+
+    pub fn open_orders(&self) -> impl Iterator<Item = &OpenOrderSlot> {
+        self.orders.iter().filter(|slot| slot.order_id != 0)
+    }
+
+    // Records a newly resting order, keeping the owner within `max_orders`
+    pub fn add_order(
+        &mut self,
+        order_id: u128,
+        is_bid: bool,
+        client_order_id: u64,
+        max_orders: u8,
+    ) -> Result<()> {
+        require!(
+            self.open_orders().count() < max_orders as usize,
+            ErrorCode::TooManyOpenOrders
+        );
+        let slot = self
+            .orders
+            .iter_mut()
+            .find(|slot| slot.order_id == 0)
+            .ok_or(ErrorCode::TooManyOpenOrders)?;
+        *slot = OpenOrderSlot {
+            order_id,
+            is_bid,
+            client_order_id,
+        };
+        Ok(())
+    }
+
+    // Frees the slot of an order that has left the book, if it's tracked
+    pub fn remove_order(&mut self, order_id: u128) {
+        if let Some(slot) = self
+            .orders
+            .iter_mut()
+            .find(|slot| slot.order_id == order_id)
+        {
+            *slot = OpenOrderSlot::default();
+        }
+    }
+
+    pub fn find_order_mut(&mut self, order_id: u128) -> Option<&mut OpenOrderSlot> {
+        self.orders
+            .iter_mut()
+            .find(|slot| slot.order_id == order_id)
+    }
+
+    // Takes up to `amount` from the free quote (for a bid) or base (for an
+    // ask) balance, returning how much was taken
+    pub fn take_free(&mut self, is_bid: bool, amount: u64) -> u64 {
+        let free = if is_bid {
+            &mut self.quote_free
+        } else {
+            &mut self.base_free
+        };
+        let taken = core::cmp::min(amount, *free);
+        *free -= taken;
+        taken
+    }
+
+    // Moves the funds backing a removed resting order from locked to free
+    pub fn release_locked(&mut self, is_bid: bool, amount: u64) -> Result<()> {
+        if is_bid {
+            self.quote_locked = self
+                .quote_locked
+                .checked_sub(amount)
+                .ok_or(ErrorCode::InsufficientFunds)?;
+            self.quote_free = self
+                .quote_free
+                .checked_add(amount)
+                .ok_or(ErrorCode::MathOverflow)?;
+        } else {
+            self.base_locked = self
+                .base_locked
+                .checked_sub(amount)
+                .ok_or(ErrorCode::InsufficientFunds)?;
+            self.base_free = self
+                .base_free
+                .checked_add(amount)
+                .ok_or(ErrorCode::MathOverflow)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum OrderType {
+    // Match what crosses, then rest the remainder on the book
+    Limit,
+    // Match what crosses and drop the remainder
+    ImmediateOrCancel,
+    // Match the full size immediately or fail
+    FillOrKill,
+    // Rest on the book without matching, failing if it would cross
+    PostOnly,
+    // Rest on the book without matching, repriced to avoid crossing
+    PostOnlySlide,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum SelfTradeBehavior {
+    // Shrink both the taker order and the signer's resting order by the
+    // overlap without trading
+    DecrementTake,
+    // Cancel the signer's resting order and keep matching
+    CancelProvide,
+    // Fail the whole instruction
+    AbortTransaction,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum OrderBookFullPolicy {
+    // Fail any order that would rest on a full book
+    Reject,
+    // Evict the worst-priced resting order when the new order improves on it
+    EvictWorst,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum MarketStatus {
+    // Orders can be placed and cancelled
+    Active,
+    // No new orders, but resting ones can be cancelled or reduced
+    CancelOnly,
+    // Resting orders can't be changed either; funds can still be settled
+    Paused,
+}
+
+// The parameters the market authority can change after initialization
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub struct MarketConfig {
+    pub status: MarketStatus,
+    pub book_full_policy: OrderBookFullPolicy,
+    pub tick_size: u64,
+    pub base_lot_size: u64,
+    pub quote_lot_size: u64,
+    pub min_base_order_size: u64,
+    pub max_orders_per_user: u8,
+    pub maker_fee_bps: i16,
+    pub taker_fee_bps: u16,
+    pub referrer_share_bps: u16,
+}
+
 #[account]
 #[derive(InitSpace)]
 pub struct Market {
     pub authority: Pubkey,
-    pub base_mint: Pubkey,  // sol
-    pub quote_mint: Pubkey, // usdc
+    pub pending_authority: Pubkey, // proposed by `authority`, default when none is
+    pub base_mint: Pubkey,         // sol
+    pub quote_mint: Pubkey,        // usdc
     pub base_vault: Pubkey,
     pub quote_vault: Pubkey,
     pub bids: Pubkey, // orderbook
     pub asks: Pubkey, // orderbook
+    pub event_queue: Pubkey,
+    pub base_decimals: u8,
+    pub quote_decimals: u8,
+    pub tick_size: u64, // prices must be a multiple of this, in quote lots per base lot
+    pub base_lot_size: u64, // base tokens per base lot
+    pub quote_lot_size: u64, // quote tokens per quote lot
+    pub min_base_order_size: u64, // smallest order, in base lots
+    pub maker_fee_bps: i16, // negative for a rebate
+    pub taker_fee_bps: u16,
+    pub referrer_share_bps: u16, // share of the taker fee paid to a referrer
+    pub fees_accrued: u64,       // quote tokens owed to the protocol, swept by `collect_fees`
+    pub max_orders_per_user: u8, // resting orders per OpenOrders, at most MAX_OPEN_ORDERS
+    pub seq_num: u64,            // next order sequence number
+    pub event_seq_num: u64,      // next sequence number for emitted events
+    pub book_full_policy: OrderBookFullPolicy,
+    pub status: MarketStatus,
     pub bump: u8,
 }
+
+impl Market {
+    pub fn config(&self) -> MarketConfig {
+        MarketConfig {
+            status: self.status,
+            book_full_policy: self.book_full_policy,
+            tick_size: self.tick_size,
+            base_lot_size: self.base_lot_size,
+            quote_lot_size: self.quote_lot_size,
+            min_base_order_size: self.min_base_order_size,
+            max_orders_per_user: self.max_orders_per_user,
+            maker_fee_bps: self.maker_fee_bps,
+            taker_fee_bps: self.taker_fee_bps,
+            referrer_share_bps: self.referrer_share_bps,
+        }
+    }
+
+    pub fn set_config(&mut self, config: MarketConfig) {
+        self.status = config.status;
+        self.book_full_policy = config.book_full_policy;
+        self.tick_size = config.tick_size;
+        self.base_lot_size = config.base_lot_size;
+        self.quote_lot_size = config.quote_lot_size;
+        self.min_base_order_size = config.min_base_order_size;
+        self.max_orders_per_user = config.max_orders_per_user;
+        self.maker_fee_bps = config.maker_fee_bps;
+        self.taker_fee_bps = config.taker_fee_bps;
+        self.referrer_share_bps = config.referrer_share_bps;
+    }
+
+    pub fn validate_config(&self) -> Result<()> {
+        require!(
+            self.tick_size > 0
+                && self.base_lot_size > 0
+                && self.quote_lot_size > 0
+                && self.min_base_order_size > 0
+                && self.max_orders_per_user > 0
+                && self.max_orders_per_user as usize <= MAX_OPEN_ORDERS,
+            ErrorCode::InvalidMarketConfig
+        );
+        // A maker rebate is paid out of the taker fee on the same fill
+        require!(
+            (self.taker_fee_bps as u64) <= FEE_BPS_DENOMINATOR
+                && self.maker_fee_bps as i64 <= FEE_BPS_DENOMINATOR as i64
+                && -(self.maker_fee_bps as i64) <= self.taker_fee_bps as i64,
+            ErrorCode::InvalidFeeConfig
+        );
+        require!(
+            (self.referrer_share_bps as u64) <= FEE_BPS_DENOMINATOR,
+            ErrorCode::InvalidFeeConfig
+        );
+        Ok(())
+    }
+
+    // Sequence numbers stay below 2^63 so the top bit of an order ID's lower
+    // half is free to encode the side
+    pub fn next_seq_num(&mut self) -> Result<u64> {
+        let seq_num = self.seq_num;
+        require!(seq_num < 1 << 63, ErrorCode::MathOverflow);
+        self.seq_num = seq_num + 1;
+        Ok(seq_num)
+    }
+
+    pub fn next_event_seq_num(&mut self) -> u64 {
+        let seq_num = self.event_seq_num;
+        self.event_seq_num = seq_num.wrapping_add(1);
+        seq_num
+    }
+
+    // Converts a fixed-point price in quote tokens per base token into quote
+    // lots per base lot, the unit the book is kept in. The normalization
+    // factor accounts for the mints' decimals and the lot sizes; prices that
+    // don't land on a whole number of quote lots are rejected.
+    pub fn price_in_lots(&self, price: u64) -> Result<u64> {
+        let numerator = 10u128
+            .checked_pow(self.quote_decimals as u32)
+            .and_then(|n| n.checked_mul(self.base_lot_size as u128))
+            .and_then(|n| n.checked_mul(price as u128))
+            .ok_or(ErrorCode::MathOverflow)?;
+        let denominator = 10u128
+            .checked_pow(self.base_decimals as u32 + PRICE_DECIMALS)
+            .and_then(|d| d.checked_mul(self.quote_lot_size as u128))
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        require!(numerator % denominator == 0, ErrorCode::InvalidPrice);
+        Ok(u64::try_from(numerator / denominator).map_err(|_| ErrorCode::MathOverflow)?)
+    }
+
+    // Orders are sized in base lots and priced in quote lots per base lot;
+    // these convert them to the token amounts that actually move
+    pub fn base_amount(&self, base_lots: u64) -> Result<u64> {
+        Ok(base_lots
+            .checked_mul(self.base_lot_size)
+            .ok_or(ErrorCode::MathOverflow)?)
+    }
+
+    pub fn quote_amount(&self, price: u64, base_lots: u64) -> Result<u64> {
+        Ok(price
+            .checked_mul(base_lots)
+            .and_then(|quote_lots| quote_lots.checked_mul(self.quote_lot_size))
+            .ok_or(ErrorCode::MathOverflow)?)
+    }
+
+    // Taker fee on `quote_amount` traded, rounded up
+    pub fn taker_fee(&self, quote_amount: u64) -> Result<u64> {
+        let fee = (quote_amount as u128 * self.taker_fee_bps as u128)
+            .div_ceil(FEE_BPS_DENOMINATOR as u128);
+        Ok(u64::try_from(fee).map_err(|_| ErrorCode::MathOverflow)?)
+    }
+
+    // Referrer's cut of the taker fees paid on an order
+    pub fn referrer_fee(&self, taker_fees: u64) -> u64 {
+        (taker_fees as u128 * self.referrer_share_bps as u128 / FEE_BPS_DENOMINATOR as u128) as u64
+    }
+
+    // Maker fee for `base_lots` filled at `price` on an order resting at
+    // `maker_fee_bps`, negative for a rebate. It is charged per lot, rounded
+    // against the maker, so a bid's locked fee reserve is used up exactly
+    // however the order gets filled.
+    pub fn maker_fee(&self, maker_fee_bps: i16, price: u64, base_lots: u64) -> Result<i64> {
+        let lot_quote_amount = self.quote_amount(price, 1)? as u128;
+        let bps = maker_fee_bps.unsigned_abs() as u128;
+        let lot_fee = if maker_fee_bps >= 0 {
+            (lot_quote_amount * bps).div_ceil(FEE_BPS_DENOMINATOR as u128)
+        } else {
+            lot_quote_amount * bps / FEE_BPS_DENOMINATOR as u128
+        };
+        let fee = lot_fee
+            .checked_mul(base_lots as u128)
+            .and_then(|fee| i64::try_from(fee).ok())
+            .ok_or(ErrorCode::MathOverflow)?;
+        Ok(if maker_fee_bps >= 0 { fee } else { -fee })
+    }
+
+    // Funds locked behind `base_lots` of a resting order
+    pub fn order_locked_amount(&self, order: &Order, base_lots: u64) -> Result<u64> {
+        if Order::is_bid_order_id(order.order_id) {
+            self.bid_locked_amount(order.maker_fee_bps, order.price, base_lots)
+        } else {
+            self.base_amount(base_lots)
+        }
+    }
+
+    // Funds an out event hands back to the maker: what its order still had
+    // locked
+    pub fn out_event_locked_amount(&self, event: &Event) -> Result<u64> {
+        if event.is_bid() {
+            self.bid_locked_amount(event.maker_fee_bps, event.price, event.size)
+        } else {
+            self.base_amount(event.size)
+        }
+    }
+
+    // Quote locked behind a resting bid: its cost plus the maker fee it may owe
+    pub fn bid_locked_amount(&self, maker_fee_bps: i16, price: u64, base_lots: u64) -> Result<u64> {
+        let fee = self.maker_fee(maker_fee_bps, price, base_lots)?.max(0) as u64;
+        Ok(self
+            .quote_amount(price, base_lots)?
+            .checked_add(fee)
+            .ok_or(ErrorCode::MathOverflow)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A SOL/USDC-like market: 9 and 6 decimals, 0.01 SOL base lots and
+    // 0.000001 USDC quote lots
+    fn sol_usdc_market() -> Market {
+        Market {
+            authority: Pubkey::default(),
+            pending_authority: Pubkey::default(),
+            base_mint: Pubkey::default(),
+            quote_mint: Pubkey::default(),
+            base_vault: Pubkey::default(),
+            quote_vault: Pubkey::default(),
+            bids: Pubkey::default(),
+            asks: Pubkey::default(),
+            event_queue: Pubkey::default(),
+            base_decimals: 9,
+            quote_decimals: 6,
+            tick_size: 1,
+            base_lot_size: 10_000_000,
+            quote_lot_size: 1,
+            min_base_order_size: 1,
+            maker_fee_bps: 0,
+            taker_fee_bps: 0,
+            referrer_share_bps: 0,
+            fees_accrued: 0,
+            max_orders_per_user: 8,
+            seq_num: 0,
+            event_seq_num: 0,
+            book_full_policy: OrderBookFullPolicy::Reject,
+            status: MarketStatus::Active,
+            bump: 0,
+        }
+    }
+
+    #[test]
+    fn human_price_maps_exactly_to_quote_lots() {
+        let market = sol_usdc_market();
+
+        // 142.35 USDC per SOL is 1.4235 USDC, or 1_423_500 quote lots, per
+        // 0.01 SOL base lot
+        let price = market.price_in_lots(142_350_000).unwrap();
+        assert_eq!(price, 1_423_500);
+
+        // 1 SOL is 100 base lots and costs exactly 142.35 USDC
+        assert_eq!(market.base_amount(100).unwrap(), 1_000_000_000);
+        assert_eq!(market.quote_amount(price, 100).unwrap(), 142_350_000);
+        assert_eq!(market.quote_amount(price, 1).unwrap(), 1_423_500);
+    }
+
+    #[test]
+    fn price_off_the_quote_lot_grid_is_rejected() {
+        let market = sol_usdc_market();
+
+        // 142.350001 USDC per SOL would be 1_423_500.01 quote lots per base lot
+        let err = market.price_in_lots(142_350_001).unwrap_err();
+        assert_eq!(err, ErrorCode::InvalidPrice.into());
+
+        // The next price on the grid is 100 units up
+        assert_eq!(market.price_in_lots(142_350_100).unwrap(), 1_423_501);
+    }
+}
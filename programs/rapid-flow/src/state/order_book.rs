@@ -0,0 +1,730 @@
+use anchor_lang::prelude::*;
+
+use crate::{error::ErrorCode, OrderBookFullPolicy};
+
+pub const MAX_ORDERS: usize = 1024;
+
+// Child handles with this bit set point into `orders` (leaves), otherwise
+// into `nodes` (inner nodes)
+const LEAF_BIT: u32 = 1 << 31;
+
+// Order books are too large to create through CPI, so the client allocates
+// them up front with this much space and `initialize` claims them
+pub const ORDER_BOOK_SPACE: usize = 8 + std::mem::size_of::<OrderBook>();
+
+#[account(zero_copy)]
+pub struct OrderBook {
+    // book: a critbit tree keyed by order ID, with orders as its leaves
+    pub market: Pubkey,
+    pub is_bid: u8,
+    pub _padding: [u8; 3],
+    pub root: u32,
+    pub order_count: u32,
+    pub orders_used: u32, // high-water mark of `orders`
+    pub nodes_used: u32,  // high-water mark of `nodes`
+    pub free_order_count: u32,
+    pub free_node_count: u32,
+    pub _reserved: [u8; 4],
+    pub orders: [Order; MAX_ORDERS],
+    pub nodes: [InnerNode; MAX_ORDERS],
+    pub free_orders: [u32; MAX_ORDERS],
+    pub free_nodes: [u32; MAX_ORDERS],
+}
+
+#[zero_copy]
+pub struct InnerNode {
+    // every key below this node shares its first `prefix_len` bits with `key`
+    pub key: u128,
+    pub prefix_len: u32,
+    pub children: [u32; 2],
+    pub _padding: [u8; 4],
+}
+
+#[zero_copy]
+pub struct Order {
+    // single order
+    pub order_id: u128,
+    pub owner: Pubkey,
+    pub price: u64, // quote lots per base lot
+    pub size: u64,  // base lots, zero for unused slots
+    pub timestamp: i64,
+    pub client_order_id: u64,  // chosen by the owner, not necessarily unique
+    pub expiry_timestamp: i64, // zero for orders that never expire
+    pub maker_fee_bps: i16,    // market maker fee when the order was placed
+    pub _padding: [u8; 6],
+}
+
+impl Order {
+    // Order IDs use the Serum/OpenBook key layout: price in the upper 64 bits,
+    // market sequence number in the lower 64. Bid sequence numbers are
+    // bit-inverted so earlier bids at the same price get larger IDs, which also
+    // sets the top bit of the lower half and makes the side recoverable.
+    pub fn new_order_id(is_bid: bool, price: u64, seq_num: u64) -> u128 {
+        let seq = if is_bid { !seq_num } else { seq_num };
+        ((price as u128) << 64) | seq as u128
+    }
+
+    pub fn is_bid_order_id(order_id: u128) -> bool {
+        (order_id as u64) >> 63 == 1
+    }
+
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expiry_timestamp != 0 && now >= self.expiry_timestamp
+    }
+}
+
+fn is_leaf(handle: u32) -> bool {
+    handle & LEAF_BIT != 0
+}
+
+fn leaf_index(handle: u32) -> usize {
+    (handle & !LEAF_BIT) as usize
+}
+
+// Direction to take at a node whose children first differ at bit `prefix_len`
+fn crit_bit(key: u128, prefix_len: u32) -> usize {
+    ((key >> (127 - prefix_len)) & 1) as usize
+}
+
+impl OrderBook {
+    pub fn is_bid(&self) -> bool {
+        self.is_bid != 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order_count == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.order_count as usize == MAX_ORDERS
+    }
+
+    // Order IDs already sort in price-time priority, so the best order is the
+    // highest key for bids and the lowest key for asks
+    pub fn best_order(&self) -> Option<&Order> {
+        let handle = self.edge_leaf(self.is_bid())?;
+        Some(&self.orders[leaf_index(handle)])
+    }
+
+    // The order that would be matched last: lowest bid or highest ask
+    pub fn worst_order(&self) -> Option<&Order> {
+        let handle = self.edge_leaf(!self.is_bid())?;
+        Some(&self.orders[leaf_index(handle)])
+    }
+
+    pub fn find_order(&self, order_id: u128) -> Option<&Order> {
+        let index = self.find_leaf(order_id)?;
+        Some(&self.orders[index])
+    }
+
+    pub fn find_order_mut(&mut self, order_id: u128) -> Option<&mut Order> {
+        let index = self.find_leaf(order_id)?;
+        Some(&mut self.orders[index])
+    }
+
+    // Walks the book best price first
+    pub fn iter(&self) -> OrderBookIter<'_> {
+        let stack = if self.is_empty() {
+            Vec::new()
+        } else {
+            vec![self.root]
+        };
+        OrderBookIter { book: self, stack }
+    }
+
+    pub fn insert_order(&mut self, order: Order) -> Result<()> {
+        require!(!self.is_full(), ErrorCode::OrderBookFull);
+        let key = order.order_id;
+
+        if self.is_empty() {
+            self.root = self.alloc_order(order);
+            self.order_count = 1;
+            return Ok(());
+        }
+
+        // The closest existing key tells us the first bit where the new key
+        // diverges from the tree
+        let closest = self.orders[leaf_index(self.descend(key))].order_id;
+        require!(closest != key, ErrorCode::DuplicateOrderId);
+        let prefix_len = (closest ^ key).leading_zeros();
+
+        // Walk down again to where a node splitting on that bit belongs
+        let mut parent: Option<(usize, usize)> = None;
+        let mut handle = self.root;
+        while !is_leaf(handle) && self.nodes[handle as usize].prefix_len < prefix_len {
+            let node = &self.nodes[handle as usize];
+            let dir = crit_bit(key, node.prefix_len);
+            parent = Some((handle as usize, dir));
+            handle = node.children[dir];
+        }
+
+        let leaf = self.alloc_order(order);
+        let mut children = [handle; 2];
+        children[crit_bit(key, prefix_len)] = leaf;
+        let inner = self.alloc_node(InnerNode {
+            key,
+            prefix_len,
+            children,
+            _padding: [0; 4],
+        });
+
+        match parent {
+            Some((node, dir)) => self.nodes[node].children[dir] = inner,
+            None => self.root = inner,
+        }
+        self.order_count += 1;
+
+        Ok(())
+    }
+
+    pub fn remove_order(&mut self, order_id: u128) -> Option<Order> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let mut grandparent: Option<(usize, usize)> = None;
+        let mut parent: Option<(usize, usize)> = None;
+        let mut handle = self.root;
+        while !is_leaf(handle) {
+            let node = &self.nodes[handle as usize];
+            let dir = crit_bit(order_id, node.prefix_len);
+            grandparent = parent;
+            parent = Some((handle as usize, dir));
+            handle = node.children[dir];
+        }
+
+        let index = leaf_index(handle);
+        if self.orders[index].order_id != order_id {
+            return None;
+        }
+        let order = self.orders[index];
+        self.free_order(index);
+
+        // Splice the removed leaf's sibling into its parent's place
+        if let Some((node, dir)) = parent {
+            let sibling = self.nodes[node].children[1 - dir];
+            match grandparent {
+                Some((grandparent, grandparent_dir)) => {
+                    self.nodes[grandparent].children[grandparent_dir] = sibling
+                }
+                None => self.root = sibling,
+            }
+            self.free_node(node);
+        }
+        self.order_count -= 1;
+
+        Some(order)
+    }
+
+    // Removes the worst-priced order to make room for one at `price`, if
+    // `policy` allows it and `price` improves on it
+    pub fn evict_worst_order(&mut self, price: u64, policy: OrderBookFullPolicy) -> Result<Order> {
+        require!(
+            policy == OrderBookFullPolicy::EvictWorst,
+            ErrorCode::OrderBookFull
+        );
+
+        let worst_order = *self.worst_order().ok_or(ErrorCode::OrderBookFull)?;
+        let improves = if self.is_bid() {
+            price > worst_order.price
+        } else {
+            price < worst_order.price
+        };
+        require!(improves, ErrorCode::OrderBookFull);
+
+        self.remove_order(worst_order.order_id);
+        Ok(worst_order)
+    }
+
+    // Takes `size` off a resting order, removing it once nothing is left
+    pub fn reduce_order(&mut self, order_id: u128, size: u64) -> Result<()> {
+        let order = self
+            .find_order_mut(order_id)
+            .ok_or(ErrorCode::OrderNotFound)?;
+        order.size = order
+            .size
+            .checked_sub(size)
+            .ok_or(ErrorCode::MathOverflow)?;
+        if order.size == 0 {
+            self.remove_order(order_id);
+        }
+        Ok(())
+    }
+
+    // Follows `key`'s bits down to the leaf sharing the longest prefix with it
+    fn descend(&self, key: u128) -> u32 {
+        let mut handle = self.root;
+        while !is_leaf(handle) {
+            let node = &self.nodes[handle as usize];
+            handle = node.children[crit_bit(key, node.prefix_len)];
+        }
+        handle
+    }
+
+    fn find_leaf(&self, order_id: u128) -> Option<usize> {
+        if self.is_empty() {
+            return None;
+        }
+        let index = leaf_index(self.descend(order_id));
+        (self.orders[index].order_id == order_id).then_some(index)
+    }
+
+    // Leaf with the highest key when `max` is set, otherwise the lowest
+    fn edge_leaf(&self, max: bool) -> Option<u32> {
+        if self.is_empty() {
+            return None;
+        }
+        let mut handle = self.root;
+        while !is_leaf(handle) {
+            handle = self.nodes[handle as usize].children[max as usize];
+        }
+        Some(handle)
+    }
+
+    fn alloc_order(&mut self, order: Order) -> u32 {
+        let index = if self.free_order_count > 0 {
+            self.free_order_count -= 1;
+            self.free_orders[self.free_order_count as usize]
+        } else {
+            self.orders_used += 1;
+            self.orders_used - 1
+        };
+        self.orders[index as usize] = order;
+        index | LEAF_BIT
+    }
+
+    fn free_order(&mut self, index: usize) {
+        self.orders[index].size = 0;
+        self.free_orders[self.free_order_count as usize] = index as u32;
+        self.free_order_count += 1;
+    }
+
+    fn alloc_node(&mut self, node: InnerNode) -> u32 {
+        let index = if self.free_node_count > 0 {
+            self.free_node_count -= 1;
+            self.free_nodes[self.free_node_count as usize]
+        } else {
+            self.nodes_used += 1;
+            self.nodes_used - 1
+        };
+        self.nodes[index as usize] = node;
+        index
+    }
+
+    fn free_node(&mut self, index: usize) {
+        self.free_nodes[self.free_node_count as usize] = index as u32;
+        self.free_node_count += 1;
+    }
+}
+
+pub struct OrderBookIter<'a> {
+    book: &'a OrderBook,
+    stack: Vec<u32>,
+}
+
+impl<'a> Iterator for OrderBookIter<'a> {
+    type Item = &'a Order;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Push the worse child first so the better one is visited first
+        let best_dir = self.book.is_bid() as usize;
+        while let Some(handle) = self.stack.pop() {
+            if is_leaf(handle) {
+                return Some(&self.book.orders[leaf_index(handle)]);
+            }
+            let node = &self.book.nodes[handle as usize];
+            self.stack.push(node.children[1 - best_dir]);
+            self.stack.push(node.children[best_dir]);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{error::ErrorCode, *};
+    use bytemuck::Zeroable;
+
+    fn new_book(is_bid: bool) -> Box<OrderBook> {
+        let mut book = Box::new(OrderBook::zeroed());
+        book.is_bid = is_bid as u8;
+        book
+    }
+
+    fn new_order(is_bid: bool, price: u64, seq_num: u64) -> Order {
+        Order {
+            order_id: Order::new_order_id(is_bid, price, seq_num),
+            price,
+            size: 1,
+            ..Order::zeroed()
+        }
+    }
+
+    fn assert_error(result: Result<()>, expected: ErrorCode) {
+        match result {
+            Err(Error::AnchorError(err)) => {
+                assert_eq!(err.error_code_number, u32::from(expected))
+            }
+            other => panic!("expected {expected:?}, got {other:?}"),
+        }
+    }
+
+    // xorshift64, so runs are repeatable without a rand dependency
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+    }
+
+    // Checks the book against the reference IDs, which must be in
+    // price-time priority: highest bid ID first, lowest ask ID first
+    fn assert_book(book: &OrderBook, expected: &[u128]) {
+        let ids: Vec<u128> = book.iter().map(|o| o.order_id).collect();
+        assert_eq!(ids, expected);
+        assert_eq!(book.order_count as usize, expected.len());
+        assert_eq!(
+            book.best_order().map(|o| o.order_id),
+            expected.first().copied()
+        );
+        assert_eq!(
+            book.worst_order().map(|o| o.order_id),
+            expected.last().copied()
+        );
+
+        // Every slot is either in the tree or on a free list
+        assert_eq!(
+            (book.free_order_count + book.order_count) as usize,
+            book.orders_used as usize
+        );
+        let inner_nodes = expected.len().saturating_sub(1);
+        assert_eq!(
+            book.free_node_count as usize + inner_nodes,
+            book.nodes_used as usize
+        );
+        assert!(book.orders_used as usize <= MAX_ORDERS);
+    }
+
+    fn sort_by_priority(is_bid: bool, ids: &mut [u128]) {
+        ids.sort_unstable();
+        if is_bid {
+            ids.reverse();
+        }
+    }
+
+    #[test]
+    fn randomized_inserts_and_removes_match_a_sorted_reference() {
+        for is_bid in [true, false] {
+            let mut book = new_book(is_bid);
+            let mut reference: Vec<u128> = Vec::new();
+            let mut rng = Rng(0x9e37_79b9_7f4a_7c15 + is_bid as u64);
+            let mut seq_num = 0;
+
+            for step in 0..20_000 {
+                let insert =
+                    reference.is_empty() || (reference.len() < MAX_ORDERS && rng.below(100) < 55);
+                if insert {
+                    // Few distinct prices, so time priority is exercised too
+                    let order = new_order(is_bid, 1 + rng.below(40) as u64, seq_num);
+                    seq_num += 1;
+                    book.insert_order(order).unwrap();
+                    // Keep the reference in priority order as it grows
+                    let key = order.order_id;
+                    let position =
+                        reference.partition_point(|&id| if is_bid { id > key } else { id < key });
+                    reference.insert(position, key);
+                } else {
+                    let order_id = reference.remove(rng.below(reference.len()));
+                    let removed = book.remove_order(order_id).map(|o| o.order_id);
+                    assert_eq!(removed, Some(order_id));
+                    assert!(book.remove_order(order_id).is_none());
+                }
+
+                if step % 16 == 0 {
+                    assert_book(&book, &reference);
+                } else {
+                    assert_eq!(
+                        book.best_order().map(|o| o.order_id),
+                        reference.first().copied()
+                    );
+                    assert_eq!(
+                        book.worst_order().map(|o| o.order_id),
+                        reference.last().copied()
+                    );
+                }
+            }
+            assert_book(&book, &reference);
+        }
+    }
+
+    #[test]
+    fn free_slots_are_reused_across_fill_and_drain_cycles() {
+        let mut book = new_book(false);
+        let mut rng = Rng(42);
+        let mut seq_num = 0;
+
+        for _ in 0..3 {
+            let mut reference: Vec<u128> = Vec::new();
+            while !book.is_full() {
+                let order = new_order(false, 1 + rng.below(1000) as u64, seq_num);
+                seq_num += 1;
+                book.insert_order(order).unwrap();
+                reference.push(order.order_id);
+            }
+            assert_error(
+                book.insert_order(new_order(false, 1, seq_num)),
+                ErrorCode::OrderBookFull,
+            );
+            sort_by_priority(false, &mut reference);
+            assert_book(&book, &reference);
+
+            while !reference.is_empty() {
+                let order_id = reference.remove(rng.below(reference.len()));
+                assert!(book.remove_order(order_id).is_some());
+            }
+            assert_book(&book, &[]);
+        }
+
+        assert_eq!(book.orders_used as usize, MAX_ORDERS);
+        assert_eq!(book.nodes_used as usize, MAX_ORDERS - 1);
+    }
+
+    #[test]
+    fn removing_at_root_and_inner_positions_keeps_the_tree_intact() {
+        let ids: Vec<u128> = (0..8)
+            .map(|seq_num| Order::new_order_id(false, 10 + seq_num % 4, seq_num))
+            .collect();
+        let mut sorted = ids.clone();
+        sort_by_priority(false, &mut sorted);
+
+        // Remove starting from every position of the insertion order, so keys
+        // get spliced out both right below the root and deeper in the tree
+        for start in 0..ids.len() {
+            let mut book = new_book(false);
+            for (seq_num, &order_id) in ids.iter().enumerate() {
+                let mut order = new_order(false, 10 + seq_num as u64 % 4, seq_num as u64);
+                order.order_id = order_id;
+                book.insert_order(order).unwrap();
+            }
+
+            let mut reference = sorted.clone();
+            for i in 0..ids.len() {
+                let order_id = ids[(start + i) % ids.len()];
+                assert_eq!(book.remove_order(order_id).unwrap().order_id, order_id);
+                reference.retain(|&id| id != order_id);
+                assert_book(&book, &reference);
+            }
+        }
+    }
+
+    #[test]
+    fn duplicate_order_ids_are_rejected() {
+        let mut book = new_book(true);
+        let order = new_order(true, 100, 7);
+        book.insert_order(order).unwrap();
+
+        assert_error(book.insert_order(order), ErrorCode::DuplicateOrderId);
+        assert_book(&book, &[order.order_id]);
+    }
+
+    #[test]
+    fn iteration_follows_price_time_priority() {
+        let mut bids = new_book(true);
+        let mut asks = new_book(false);
+        for (seq_num, price) in [100, 102, 100, 101, 102].into_iter().enumerate() {
+            bids.insert_order(new_order(true, price, seq_num as u64))
+                .unwrap();
+            asks.insert_order(new_order(false, price, seq_num as u64))
+                .unwrap();
+        }
+
+        let bid_queue: Vec<(u64, u64)> = bids
+            .iter()
+            .map(|o| (o.price, !(o.order_id as u64)))
+            .collect();
+        assert_eq!(
+            bid_queue,
+            [(102, 1), (102, 4), (101, 3), (100, 0), (100, 2)]
+        );
+
+        let ask_queue: Vec<(u64, u64)> =
+            asks.iter().map(|o| (o.price, o.order_id as u64)).collect();
+        assert_eq!(
+            ask_queue,
+            [(100, 0), (100, 2), (101, 3), (102, 1), (102, 4)]
+        );
+    }
+
+    // A full book with one order per price from `first_price` up
+    fn full_book(is_bid: bool, first_price: u64) -> Box<OrderBook> {
+        let mut book = new_book(is_bid);
+        for seq_num in 0..MAX_ORDERS as u64 {
+            book.insert_order(new_order(is_bid, first_price + seq_num, seq_num))
+                .unwrap();
+        }
+        book
+    }
+
+    fn test_market() -> Market {
+        Market {
+            authority: Pubkey::default(),
+            pending_authority: Pubkey::default(),
+            base_mint: Pubkey::default(),
+            quote_mint: Pubkey::default(),
+            base_vault: Pubkey::default(),
+            quote_vault: Pubkey::default(),
+            bids: Pubkey::default(),
+            asks: Pubkey::default(),
+            event_queue: Pubkey::default(),
+            base_decimals: 9,
+            quote_decimals: 6,
+            tick_size: 1,
+            base_lot_size: 1_000,
+            quote_lot_size: 10,
+            min_base_order_size: 1,
+            maker_fee_bps: 25,
+            taker_fee_bps: 50,
+            referrer_share_bps: 0,
+            fees_accrued: 0,
+            max_orders_per_user: 8,
+            seq_num: 0,
+            event_seq_num: 0,
+            book_full_policy: OrderBookFullPolicy::EvictWorst,
+            status: MarketStatus::Active,
+            bump: 0,
+        }
+    }
+
+    #[test]
+    fn reject_policy_never_evicts() {
+        let mut book = full_book(true, 100);
+        let worst = book.worst_order().unwrap().order_id;
+
+        assert_error(
+            book.evict_worst_order(10_000, OrderBookFullPolicy::Reject)
+                .map(|_| ()),
+            ErrorCode::OrderBookFull,
+        );
+        assert_error(
+            book.insert_order(new_order(true, 10_000, MAX_ORDERS as u64)),
+            ErrorCode::OrderBookFull,
+        );
+        assert!(book.is_full());
+        assert_eq!(book.worst_order().unwrap().order_id, worst);
+    }
+
+    #[test]
+    fn evict_worst_removes_the_lowest_bid_and_the_highest_ask() {
+        let mut bids = full_book(true, 100);
+        let evicted = bids
+            .evict_worst_order(150, OrderBookFullPolicy::EvictWorst)
+            .unwrap();
+        assert_eq!(evicted.price, 100);
+        assert!(bids.find_order(evicted.order_id).is_none());
+        assert_eq!(bids.worst_order().unwrap().price, 101);
+        bids.insert_order(new_order(true, 150, MAX_ORDERS as u64))
+            .unwrap();
+
+        let mut asks = full_book(false, 100);
+        let highest_ask = 100 + MAX_ORDERS as u64 - 1;
+        let evicted = asks
+            .evict_worst_order(150, OrderBookFullPolicy::EvictWorst)
+            .unwrap();
+        assert_eq!(evicted.price, highest_ask);
+        assert!(asks.find_order(evicted.order_id).is_none());
+        assert_eq!(asks.worst_order().unwrap().price, highest_ask - 1);
+    }
+
+    #[test]
+    fn evict_worst_keeps_the_book_when_the_price_does_not_improve() {
+        let mut bids = full_book(true, 100);
+        assert_error(
+            bids.evict_worst_order(100, OrderBookFullPolicy::EvictWorst)
+                .map(|_| ()),
+            ErrorCode::OrderBookFull,
+        );
+        assert!(bids.is_full());
+
+        let mut asks = full_book(false, 100);
+        let highest_ask = 100 + MAX_ORDERS as u64 - 1;
+        assert_error(
+            asks.evict_worst_order(highest_ask, OrderBookFullPolicy::EvictWorst)
+                .map(|_| ()),
+            ErrorCode::OrderBookFull,
+        );
+        assert!(asks.is_full());
+    }
+
+    #[test]
+    fn evicted_order_out_event_releases_its_locked_funds() {
+        let market = test_market();
+        for is_bid in [true, false] {
+            let mut book = new_book(is_bid);
+            let owner = Pubkey::new_unique();
+            let mut open_orders = OpenOrders {
+                market: Pubkey::default(),
+                owner,
+                base_free: 0,
+                base_locked: 0,
+                quote_free: 0,
+                quote_locked: 0,
+                orders: [OpenOrderSlot::default(); MAX_OPEN_ORDERS],
+                bump: 0,
+            };
+
+            // Lock for a resting order the way `rest_order` does
+            let mut order = new_order(is_bid, 7, 0);
+            order.owner = owner;
+            order.size = 3;
+            order.maker_fee_bps = market.maker_fee_bps;
+            let locked = market.order_locked_amount(&order, order.size).unwrap();
+            if is_bid {
+                open_orders.quote_locked = locked;
+            } else {
+                open_orders.base_locked = locked;
+            }
+            book.insert_order(order).unwrap();
+            for seq_num in 1..MAX_ORDERS as u64 {
+                book.insert_order(new_order(is_bid, if is_bid { 8 } else { 6 }, seq_num))
+                    .unwrap();
+            }
+
+            let better_price = if is_bid { 8 } else { 6 };
+            let evicted = book
+                .evict_worst_order(better_price, OrderBookFullPolicy::EvictWorst)
+                .unwrap();
+            assert_eq!(evicted.order_id, order.order_id);
+
+            let mut event_queue = Box::new(EventQueue::zeroed());
+            event_queue
+                .push_back(Event::new_out(&evicted, 1_700_000_000))
+                .unwrap();
+            let event = event_queue.pop_front().unwrap();
+            assert_eq!(event.event_type, EventType::Out as u8);
+            assert_eq!(event.maker, owner);
+            assert_eq!(event.is_bid(), is_bid);
+            assert_eq!(event.maker_out, 1);
+
+            // What `consume_events` releases for the out event
+            let released = market.out_event_locked_amount(&event).unwrap();
+            assert_eq!(released, locked);
+            open_orders.release_locked(is_bid, released).unwrap();
+            assert_eq!(open_orders.base_locked + open_orders.quote_locked, 0);
+            let free = if is_bid {
+                open_orders.quote_free
+            } else {
+                open_orders.base_free
+            };
+            assert_eq!(free, locked);
+        }
+    }
+}
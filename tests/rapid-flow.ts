@@ -8,7 +8,7 @@ import {
   getOrCreateAssociatedTokenAccount,
   mintTo,
 } from "@solana/spl-token";
-import { Keypair, PublicKey } from "@solana/web3.js";
+import { Keypair, PublicKey, Transaction } from "@solana/web3.js";
 import { assert } from "chai";
 
 describe("rapid-flow", () => {
@@ -23,10 +23,25 @@ describe("rapid-flow", () => {
   let baseMint: PublicKey;
   let quoteMint: PublicKey;
   let marketPda: PublicKey;
-  let bidsPda: PublicKey;
-  let asksPda: PublicKey;
+  // Order books are too large for CPI allocation, so they are plain keypair
+  // accounts created by the client
+  const bidsKeypair = Keypair.generate();
+  const asksKeypair = Keypair.generate();
+  const eventQueueKeypair = Keypair.generate();
+  const bids = bidsKeypair.publicKey;
+  const asks = asksKeypair.publicKey;
+  const eventQueue = eventQueueKeypair.publicKey;
   let baseVault: PublicKey;
   let quoteVault: PublicKey;
+  // Prices are sent as fixed-point quote per base with PRICE_DECIMALS
+  // decimals. With a 9-decimal base, a 6-decimal quote and lot sizes of 1, a
+  // price of 1 raw quote per raw base is 1000 quote tokens per base token.
+  const PRICE_DECIMALS = 6;
+  const RAW_PRICE_SCALE = new anchor.BN(10).pow(
+    new anchor.BN(PRICE_DECIMALS + 9 - 6)
+  );
+  const toPrice = (rawPrice: number) =>
+    new anchor.BN(rawPrice).mul(RAW_PRICE_SCALE);
   // Test users configuration - SIMPLIFIED to 3 users
   const users = [
     {
@@ -159,16 +174,6 @@ describe("rapid-flow", () => {
       program.programId
     );
 
-    [bidsPda] = PublicKey.findProgramAddressSync(
-      [Buffer.from("bids"), marketPda.toBuffer()],
-      program.programId
-    );
-
-    [asksPda] = PublicKey.findProgramAddressSync(
-      [Buffer.from("asks"), marketPda.toBuffer()],
-      program.programId
-    );
-
     baseVault = await getAssociatedTokenAddress(baseMint, marketPda, true);
     quoteVault = await getAssociatedTokenAddress(quoteMint, marketPda, true);
 
@@ -256,19 +261,37 @@ describe("rapid-flow", () => {
 
   it("Market is initialized!", async () => {
     const tx = await program.methods
-      .initialize()
+      // Lot sizes of 1 so sizes and prices below are plain token amounts,
+      // and no fees so balances move by exactly the traded amounts
+      .initialize({
+        bookFullPolicy: { reject: {} },
+        tickSize: new anchor.BN(1),
+        baseLotSize: new anchor.BN(1),
+        quoteLotSize: new anchor.BN(1),
+        minBaseOrderSize: new anchor.BN(1),
+        maxOrdersPerUser: 8,
+        makerFeeBps: 0,
+        takerFeeBps: 0,
+        referrerShareBps: 0,
+      })
       .accounts({
         signer: wallet.publicKey,
         baseMint,
         quoteMint,
         //@ts-ignore
         market: marketPda,
-        bids: bidsPda,
-        asks: asksPda,
+        bids,
+        asks,
+        eventQueue,
         baseVault,
         quoteVault,
       })
-      .signers([wallet.payer])
+      .preInstructions([
+        await program.account.orderBook.createInstruction(bidsKeypair),
+        await program.account.orderBook.createInstruction(asksKeypair),
+        await program.account.eventQueue.createInstruction(eventQueueKeypair),
+      ])
+      .signers([wallet.payer, bidsKeypair, asksKeypair, eventQueueKeypair])
       .rpc();
 
     // Verify market accounts exist
@@ -283,6 +306,14 @@ describe("rapid-flow", () => {
       quoteMint.toBase58(),
       "Quote mint should match"
     );
+    assert.equal(marketAccount.baseDecimals, 9, "Base decimals should match");
+    assert.equal(marketAccount.quoteDecimals, 6, "Quote decimals should match");
+    assert.equal(Number(marketAccount.tickSize), 1, "Tick size should be set");
+    assert.equal(
+      Number(marketAccount.minBaseOrderSize),
+      1,
+      "Minimum order size should be set"
+    );
   });
 
   describe("Place Order Tests", () => {
@@ -325,44 +356,42 @@ describe("rapid-flow", () => {
           quote: Number(quoteVaultBefore.amount),
         });
 
-        const price = new anchor.BN(order.price);
+        const price = toPrice(order.price);
         const size = new anchor.BN(order.size);
 
-        // Build remaining accounts for matched orders
-        const remainingAccounts = order.matchedUsers
-          ? order.matchedUsers.map((userName) => {
-              const matchedUser = users.find((u) => u.name === userName)!;
-              return {
-                pubkey: (matchedUser as any).openOrdersPda,
-                isSigner: false,
-                isWritable: true,
-              };
-            })
-          : [];
-
-        if (order.matchedUsers) {
-        }
-
         const tx = await program.methods
-          .placeOrder(order.isBid, price, size)
+          .placeOrder({
+            isBid: order.isBid,
+            price,
+            size,
+            orderType: { limit: {} },
+            selfTradeBehavior: { decrementTake: {} },
+            clientOrderId: new anchor.BN(0),
+            expiryTimestamp: new anchor.BN(0),
+          })
           .accounts({
             signer: userPubkey,
             //@ts-ignore
             market: marketPda,
-            asks: asksPda,
-            bids: bidsPda,
+            asks,
+            bids,
+            eventQueue,
             userOpenOrders: (user as any).openOrdersPda,
             baseVault,
             quoteVault,
             userBaseVault: (user as any).baseVault,
             userQuoteVault: (user as any).quoteVault,
           })
-          .remainingAccounts(remainingAccounts)
           .signers([userWallet])
           .rpc();
 
         // Add delay and confirmation for order processing
         await connection.confirmTransaction(tx);
+
+        // Apply the makers' side of any fills
+        if (order.matchedUsers) {
+          await consumeEvents(order.matchedUsers);
+        }
         await new Promise((resolve) => setTimeout(resolve, 500)); // 500ms delay
 
         // Capture balances after order
@@ -451,6 +480,36 @@ describe("rapid-flow", () => {
     });
   });
 
+  describe("Consume Events Tests", () => {
+    it("Should fail: same OpenOrders account passed twice", async () => {
+      try {
+        await consumeEvents(["Alice", "Alice"]);
+        assert.fail("Should have thrown error");
+      } catch (err: any) {
+        assert.include(err.message, "DuplicateOpenOrders");
+      }
+    });
+
+    it("Should fail: account that is not an OpenOrders PDA", async () => {
+      try {
+        await program.methods
+          .consumeEvents(32)
+          .accounts({
+            //@ts-ignore
+            market: marketPda,
+            eventQueue,
+          })
+          .remainingAccounts([
+            { pubkey: marketPda, isSigner: false, isWritable: true },
+          ])
+          .rpc();
+        assert.fail("Should have thrown error");
+      } catch (err: any) {
+        assert.include(err.message, "AccountDiscriminatorMismatch");
+      }
+    });
+  });
+
   describe("Settle Funds Tests", () => {
     it("Alice settles all her base funds (2 base from matched trade)", async () => {
       const user = users.find((u) => u.name === "Alice")!;
@@ -655,13 +714,6 @@ describe("rapid-flow", () => {
   });
 
   describe("Cancel Order Tests", () => {
-    // Helper function to get order book and find user's orders
-    async function getUserOrders(isBid: boolean) {
-      const orderBookPda = isBid ? bidsPda : asksPda;
-      const orderBook = await program.account.orderBook.fetch(orderBookPda);
-      return orderBook.orders;
-    }
-
     it("Alice cancels her remaining bid order (300 quote locked)", async () => {
       const user = users.find((u) => u.name === "Alice")!;
       const userWallet =
@@ -672,7 +724,7 @@ describe("rapid-flow", () => {
           : (user.wallet as anchor.Wallet).publicKey;
 
       // Get Alice's orders from bids book
-      const bidsOrders = await getUserOrders(true);
+      const bidsOrders = await getBookOrders(true);
       const aliceOrder = bidsOrders.find((o: any) =>
         o.owner.equals(userPubkey)
       );
@@ -700,8 +752,8 @@ describe("rapid-flow", () => {
           signer: userPubkey,
           //@ts-ignore
           market: marketPda,
-          bids: bidsPda,
-          asks: asksPda,
+          bids,
+          asks,
           openOrders: (user as any).openOrdersPda,
           baseVault,
           quoteVault,
@@ -746,7 +798,7 @@ describe("rapid-flow", () => {
       );
 
       // Verify order removed from order book
-      const bidsOrdersAfter = await getUserOrders(true);
+      const bidsOrdersAfter = await getBookOrders(true);
       const aliceOrderAfter = bidsOrdersAfter.find(
         (o: any) =>
           o.owner.equals(userPubkey) && o.orderId.eq(aliceOrder.orderId)
@@ -767,7 +819,7 @@ describe("rapid-flow", () => {
           : (user.wallet as anchor.Wallet).publicKey;
 
       // Get Bob's orders from asks book
-      const asksOrders = await getUserOrders(false);
+      const asksOrders = await getBookOrders(false);
       const bobOrder = asksOrders.find((o: any) => o.owner.equals(userPubkey));
 
       if (!bobOrder) {
@@ -794,8 +846,8 @@ describe("rapid-flow", () => {
           signer: userPubkey,
           //@ts-ignore
           market: marketPda,
-          bids: bidsPda,
-          asks: asksPda,
+          bids,
+          asks,
           openOrders: (user as any).openOrdersPda,
           baseVault,
           quoteVault,
@@ -840,7 +892,7 @@ describe("rapid-flow", () => {
       );
 
       // Verify order removed from order book
-      const asksOrdersAfter = await getUserOrders(false);
+      const asksOrdersAfter = await getBookOrders(false);
       const bobOrderAfter = asksOrdersAfter.find(
         (o: any) => o.owner.equals(userPubkey) && o.orderId.eq(bobOrder.orderId)
       );
@@ -860,7 +912,7 @@ describe("rapid-flow", () => {
           : (user.wallet as anchor.Wallet).publicKey;
 
       // Get Bob's orders from bids book
-      const bidsOrders = await getUserOrders(true);
+      const bidsOrders = await getBookOrders(true);
       const bobOrder = bidsOrders.find((o: any) => o.owner.equals(userPubkey));
 
       if (!bobOrder) {
@@ -887,8 +939,8 @@ describe("rapid-flow", () => {
           signer: userPubkey,
           //@ts-ignore
           market: marketPda,
-          bids: bidsPda,
-          asks: asksPda,
+          bids,
+          asks,
           openOrders: (user as any).openOrdersPda,
           baseVault,
           quoteVault,
@@ -933,7 +985,7 @@ describe("rapid-flow", () => {
       );
 
       // Verify order removed from order book
-      const bidsOrdersAfter = await getUserOrders(true);
+      const bidsOrdersAfter = await getBookOrders(true);
       const bobOrderAfter = bidsOrdersAfter.find(
         (o: any) => o.owner.equals(userPubkey) && o.orderId.eq(bobOrder.orderId)
       );
@@ -962,12 +1014,12 @@ describe("rapid-flow", () => {
       const baseVaultFinal = await getAccount(connection, baseVault);
       const quoteVaultFinal = await getAccount(connection, quoteVault);
 
-      const bidsBook = await program.account.orderBook.fetch(bidsPda);
-      const asksBook = await program.account.orderBook.fetch(asksPda);
+      const bidsOrders = await getBookOrders(true);
+      const asksOrders = await getBookOrders(false);
 
       // Verify all orders are cancelled and all locked funds returned
-      assert.equal(bidsBook.orders.length, 0, "All bids should be cancelled");
-      assert.equal(asksBook.orders.length, 0, "All asks should be cancelled");
+      assert.equal(bidsOrders.length, 0, "All bids should be cancelled");
+      assert.equal(asksOrders.length, 0, "All asks should be cancelled");
       assert.equal(
         Number(baseVaultFinal.amount),
         0,
@@ -981,6 +1033,1964 @@ describe("rapid-flow", () => {
     });
   });
 
+  describe("Price Improvement Tests", () => {
+    it("Alice's bid @ 100 fills Bob's ask @ 90 and is credited the difference", async () => {
+      const alice = users.find((u) => u.name === "Alice")!;
+      const aliceBefore = await program.account.openOrders.fetch(
+        (alice as any).openOrdersPda
+      );
+      const aliceQuoteBefore = await getAccount(
+        connection,
+        (alice as any).quoteVault
+      );
+
+      await placeOrder("Bob", false, 90, 2);
+      await placeOrder("Alice", true, 100, 2, ["Bob"]);
+
+      const aliceAfter = await program.account.openOrders.fetch(
+        (alice as any).openOrdersPda
+      );
+      const aliceQuoteAfter = await getAccount(
+        connection,
+        (alice as any).quoteVault
+      );
+
+      assert.equal(
+        Number(aliceQuoteBefore.amount) - Number(aliceQuoteAfter.amount),
+        200,
+        "Alice pays her limit price up front"
+      );
+      assert.equal(
+        Number(aliceAfter.baseFree) - Number(aliceBefore.baseFree),
+        2,
+        "Alice receives 2 base"
+      );
+      assert.equal(
+        Number(aliceAfter.quoteFree) - Number(aliceBefore.quoteFree),
+        20,
+        "Alice is credited the 20 quote price improvement"
+      );
+      assert.equal(
+        Number(aliceAfter.quoteLocked),
+        Number(aliceBefore.quoteLocked),
+        "Nothing is left locked for a fully filled bid"
+      );
+
+      await assertVaultsReconcile();
+    });
+
+    it("Partially filled bid locks only the unfilled size at its limit price", async () => {
+      const alice = users.find((u) => u.name === "Alice")!;
+      const quoteBefore = await getAccount(
+        connection,
+        (alice as any).quoteVault
+      );
+      const aliceBefore = await program.account.openOrders.fetch(
+        (alice as any).openOrdersPda
+      );
+
+      await placeOrder("Bob", false, 95, 1);
+      await placeOrder("Alice", true, 100, 3, ["Bob"]);
+
+      const quoteAfter = await getAccount(
+        connection,
+        (alice as any).quoteVault
+      );
+      const aliceAfter = await program.account.openOrders.fetch(
+        (alice as any).openOrdersPda
+      );
+
+      // Alice's free quote is spent before anything is transferred
+      assert.equal(
+        Number(quoteBefore.amount) +
+          Number(aliceBefore.quoteFree) -
+          Number(quoteAfter.amount) -
+          Number(aliceAfter.quoteFree),
+        295,
+        "Alice pays 300 less the 5 quote price improvement"
+      );
+      assert.equal(
+        Number(aliceAfter.quoteLocked) - Number(aliceBefore.quoteLocked),
+        200,
+        "Remaining 2 base are locked at Alice's limit price"
+      );
+
+      await assertVaultsReconcile();
+    });
+  });
+
+  describe("Order Type Tests", () => {
+    const userPubkey = (name: string) => {
+      const user = users.find((u) => u.name === name)!;
+      return user.wallet instanceof Keypair
+        ? user.wallet.publicKey
+        : (user.wallet as anchor.Wallet).publicKey;
+    };
+
+    it("Bob's IOC ask for 3 fills Alice's 2 @ 100 and rests nothing", async () => {
+      const bob = users.find((u) => u.name === "Bob")!;
+      const bobBaseBefore = await getAccount(
+        connection,
+        (bob as any).baseVault
+      );
+      const bobBefore = await program.account.openOrders.fetch(
+        (bob as any).openOrdersPda
+      );
+
+      await placeOrder("Bob", false, 100, 3, ["Alice"], {
+        immediateOrCancel: {},
+      });
+
+      const bobBaseAfter = await getAccount(connection, (bob as any).baseVault);
+      const bobAfter = await program.account.openOrders.fetch(
+        (bob as any).openOrdersPda
+      );
+
+      assert.equal(
+        Number(bobBaseBefore.amount) - Number(bobBaseAfter.amount),
+        2,
+        "Bob only pays for the 2 base that filled"
+      );
+      assert.equal(
+        Number(bobAfter.quoteFree) - Number(bobBefore.quoteFree),
+        200,
+        "Bob receives 200 quote"
+      );
+      assert.equal(
+        Number(bobAfter.baseLocked),
+        Number(bobBefore.baseLocked),
+        "Nothing is locked for the unfilled remainder"
+      );
+      assert.isUndefined(
+        (await getBookOrders(false)).find((o: any) =>
+          o.owner.equals(userPubkey("Bob"))
+        ),
+        "IOC order should not rest on the book"
+      );
+
+      await assertVaultsReconcile();
+    });
+
+    it("Should fail: Bob's FOK bid for 3 when only 2 are offered", async () => {
+      await placeOrder("Charlie", false, 105, 2);
+
+      try {
+        await placeOrder("Bob", true, 110, 3, [], { fillOrKill: {} });
+        assert.fail("Should have thrown error");
+      } catch (err: any) {
+        assert.include(err.message, "FillOrKillNotFilled");
+      }
+
+      const charlieOrder = (await getBookOrders(false)).find((o: any) =>
+        o.owner.equals(userPubkey("Charlie"))
+      );
+      assert.equal(Number(charlieOrder.size), 2, "Charlie's ask is untouched");
+    });
+
+    it("Should fail: Bob's post-only bid @ 105 would cross", async () => {
+      try {
+        await placeOrder("Bob", true, 105, 1, [], { postOnly: {} });
+        assert.fail("Should have thrown error");
+      } catch (err: any) {
+        assert.include(err.message, "PostOnlyWouldCross");
+      }
+    });
+
+    it("Bob's post-only-slide bid @ 110 rests one tick below the best ask", async () => {
+      await placeOrder("Bob", true, 110, 1, [], { postOnlySlide: {} });
+
+      const bobOrder = (await getBookOrders(true)).find((o: any) =>
+        o.owner.equals(userPubkey("Bob"))
+      );
+      assert.equal(Number(bobOrder.price), 104, "Bid slides to 104");
+
+      await assertVaultsReconcile();
+    });
+  });
+
+  describe("Market Order Tests", () => {
+    async function placeMarketOrder(
+      userName: string,
+      isBid: boolean,
+      amount: number,
+      limitPrice: number,
+      matchedUsers: string[]
+    ) {
+      const user = users.find((u) => u.name === userName)!;
+      const userWallet =
+        user.wallet instanceof Keypair ? user.wallet : user.wallet.payer;
+      const userPubkey =
+        user.wallet instanceof Keypair
+          ? user.wallet.publicKey
+          : (user.wallet as anchor.Wallet).publicKey;
+
+      const tx = await program.methods
+        .placeMarketOrder(
+          isBid,
+          new anchor.BN(amount),
+          toPrice(limitPrice),
+          { decrementTake: {} }
+        )
+        .accounts({
+          signer: userPubkey,
+          //@ts-ignore
+          market: marketPda,
+          asks,
+          bids,
+          eventQueue,
+          userOpenOrders: (user as any).openOrdersPda,
+          baseVault,
+          quoteVault,
+          userBaseVault: (user as any).baseVault,
+          userQuoteVault: (user as any).quoteVault,
+        })
+        .signers([userWallet])
+        .rpc();
+      await connection.confirmTransaction(tx);
+      await consumeEvents(matchedUsers);
+    }
+
+    it("Alice's market buy with a 300 quote budget spends only 210", async () => {
+      const alice = users.find((u) => u.name === "Alice")!;
+      const quoteBefore = await getAccount(
+        connection,
+        (alice as any).quoteVault
+      );
+      const aliceBefore = await program.account.openOrders.fetch(
+        (alice as any).openOrdersPda
+      );
+
+      // Charlie's 2 @ 105 is all that's affordable under the 110 cap
+      await placeMarketOrder("Alice", true, 300, 110, ["Charlie"]);
+
+      const quoteAfter = await getAccount(
+        connection,
+        (alice as any).quoteVault
+      );
+      const aliceAfter = await program.account.openOrders.fetch(
+        (alice as any).openOrdersPda
+      );
+
+      assert.equal(
+        Number(quoteBefore.amount) +
+          Number(aliceBefore.quoteFree) -
+          Number(quoteAfter.amount) -
+          Number(aliceAfter.quoteFree),
+        210,
+        "Alice pays only for what filled"
+      );
+      assert.equal(
+        Number(aliceAfter.baseFree) - Number(aliceBefore.baseFree),
+        2,
+        "Alice receives 2 base"
+      );
+      assert.equal(
+        (await getBookOrders(false)).length,
+        0,
+        "Charlie's ask is fully filled"
+      );
+
+      await assertVaultsReconcile();
+    });
+
+    it("Alice's market sell of 3 base stops at the 100 price floor", async () => {
+      const alice = users.find((u) => u.name === "Alice")!;
+      const baseBefore = await getAccount(connection, (alice as any).baseVault);
+      const aliceBefore = await program.account.openOrders.fetch(
+        (alice as any).openOrdersPda
+      );
+
+      // Only Bob's 1 @ 104 is above the floor
+      await placeMarketOrder("Alice", false, 3, 100, ["Bob"]);
+
+      const baseAfter = await getAccount(connection, (alice as any).baseVault);
+      const aliceAfter = await program.account.openOrders.fetch(
+        (alice as any).openOrdersPda
+      );
+
+      assert.equal(
+        Number(baseBefore.amount) +
+          Number(aliceBefore.baseFree) -
+          Number(baseAfter.amount) -
+          Number(aliceAfter.baseFree),
+        1,
+        "Alice only sells the 1 base that filled"
+      );
+      assert.equal(
+        Number(aliceAfter.quoteFree) - Number(aliceBefore.quoteFree),
+        104,
+        "Alice receives 104 quote"
+      );
+      assert.equal(
+        Number(aliceAfter.baseLocked),
+        Number(aliceBefore.baseLocked),
+        "Market orders never rest"
+      );
+
+      await assertVaultsReconcile();
+    });
+  });
+
+  describe("Price and Lot Size Tests", () => {
+    it("Should fail: order below the minimum size", async () => {
+      try {
+        await placeOrder("Bob", true, 100, 0);
+        assert.fail("Should have thrown error");
+      } catch (err: any) {
+        assert.include(err.message, "OrderTooSmall");
+      }
+    });
+
+    it("Should fail: order with a zero price", async () => {
+      try {
+        await placeOrder("Bob", true, 0, 1);
+        assert.fail("Should have thrown error");
+      } catch (err: any) {
+        assert.include(err.message, "InvalidPrice");
+      }
+    });
+
+    it("Should fail: price finer than one quote lot per base lot", async () => {
+      // 142.35 quote per base is 0.14235 raw quote per raw base
+      try {
+        await placeOrder("Bob", true, new anchor.BN(142_350_000), 1);
+        assert.fail("Should have thrown error");
+      } catch (err: any) {
+        assert.include(err.message, "InvalidPrice");
+      }
+    });
+  });
+
+  describe("Fee Tests", () => {
+    it("Should fail: Bob collects fees without being the market authority", async () => {
+      const bob = users.find((u) => u.name === "Bob")!;
+      try {
+        await collectFees("Bob", (bob as any).quoteVault);
+        assert.fail("Should have thrown error");
+      } catch (err: any) {
+        assert.include(err.message, "UnauthorizedAccess");
+      }
+    });
+
+    it("Should fail: nothing to collect on a market without fees", async () => {
+      const alice = users.find((u) => u.name === "Alice")!;
+      try {
+        await collectFees("Alice", (alice as any).quoteVault);
+        assert.fail("Should have thrown error");
+      } catch (err: any) {
+        assert.include(err.message, "NoFeesToCollect");
+      }
+    });
+
+    it("Should fail: referrer account that doesn't hold the quote mint", async () => {
+      const charlie = users.find((u) => u.name === "Charlie")!;
+      try {
+        await placeOrder(
+          "Bob",
+          true,
+          90,
+          1,
+          [],
+          { limit: {} },
+          (charlie as any).baseVault
+        );
+        assert.fail("Should have thrown error");
+      } catch (err: any) {
+        assert.include(err.message, "ConstraintTokenMint");
+      }
+    });
+
+    it("Should fail: the market's quote vault as referrer", async () => {
+      try {
+        await placeOrder("Bob", true, 90, 1, [], { limit: {} }, quoteVault);
+        assert.fail("Should have thrown error");
+      } catch (err: any) {
+        assert.include(err.message, "InvalidReferrer");
+      }
+    });
+  });
+
+  describe("Self-Trade Tests", () => {
+    const bobAsk = async () => {
+      const bob = users.find((u) => u.name === "Bob")!;
+      const bobPubkey = (bob.wallet as Keypair).publicKey;
+      return (await getBookOrders(false)).find((o: any) =>
+        o.owner.equals(bobPubkey)
+      );
+    };
+
+    it("Should fail: Bob's bid crosses his own ask with AbortTransaction", async () => {
+      await placeOrder("Bob", false, 120, 2);
+
+      try {
+        await placeOrder("Bob", true, 120, 1, [], { limit: {} }, null, {
+          abortTransaction: {},
+        });
+        assert.fail("Should have thrown error");
+      } catch (err: any) {
+        assert.include(err.message, "WouldSelfTrade");
+      }
+      assert.equal(Number((await bobAsk()).size), 2, "Bob's ask is untouched");
+    });
+
+    it("DecrementTake shrinks both of Bob's orders without trading", async () => {
+      const bob = users.find((u) => u.name === "Bob")!;
+      const quoteBefore = await getAccount(connection, (bob as any).quoteVault);
+      const bobBefore = await program.account.openOrders.fetch(
+        (bob as any).openOrdersPda
+      );
+
+      await placeOrder("Bob", true, 120, 1, [], { limit: {} }, null, {
+        decrementTake: {},
+      });
+
+      const quoteAfter = await getAccount(connection, (bob as any).quoteVault);
+      const bobAfter = await program.account.openOrders.fetch(
+        (bob as any).openOrdersPda
+      );
+
+      assert.equal(Number((await bobAsk()).size), 1, "Bob's ask shrinks to 1");
+      assert.equal(
+        Number(quoteAfter.amount),
+        Number(quoteBefore.amount),
+        "Bob pays nothing for the decremented bid"
+      );
+      assert.equal(
+        Number(bobBefore.baseLocked) - Number(bobAfter.baseLocked),
+        1,
+        "The decremented base is unlocked"
+      );
+      assert.equal(
+        Number(bobAfter.baseFree) - Number(bobBefore.baseFree),
+        1,
+        "The decremented base is freed"
+      );
+      assert.isUndefined(
+        (await getBookOrders(true)).find((o: any) =>
+          o.owner.equals((bob.wallet as Keypair).publicKey)
+        ),
+        "Nothing of Bob's bid rests"
+      );
+
+      await assertVaultsReconcile();
+    });
+
+    it("CancelProvide cancels Bob's own ask and keeps matching", async () => {
+      await placeOrder(
+        "Bob",
+        true,
+        120,
+        1,
+        [],
+        { immediateOrCancel: {} },
+        null,
+        { cancelProvide: {} }
+      );
+
+      assert.isUndefined(await bobAsk(), "Bob's ask is cancelled");
+
+      await assertVaultsReconcile();
+    });
+  });
+
+  describe("Client Order ID Tests", () => {
+    const cancelByClientId = async (
+      userName: string,
+      clientOrderId: number
+    ) => {
+      const user = users.find((u) => u.name === userName)!;
+      const userWallet =
+        user.wallet instanceof Keypair ? user.wallet : user.wallet.payer;
+
+      const tx = await program.methods
+        .cancelOrderByClientId(new anchor.BN(clientOrderId))
+        .accounts({
+          signer: userWallet.publicKey,
+          //@ts-ignore
+          market: marketPda,
+          bids,
+          asks,
+          openOrders: (user as any).openOrdersPda,
+          baseVault,
+          quoteVault,
+          userBaseVault: (user as any).baseVault,
+          userQuoteVault: (user as any).quoteVault,
+        })
+        .signers([userWallet])
+        .rpc();
+      await connection.confirmTransaction(tx);
+    };
+
+    it("Charlie cancels his ask by its client order ID", async () => {
+      const charlie = users.find((u) => u.name === "Charlie")!;
+      await placeOrder(
+        "Charlie",
+        false,
+        130,
+        2,
+        [],
+        { limit: {} },
+        null,
+        { decrementTake: {} },
+        42
+      );
+
+      const charlieOrder = (await getBookOrders(false)).find((o: any) =>
+        o.owner.equals((charlie.wallet as Keypair).publicKey)
+      );
+      assert.equal(
+        Number(charlieOrder.clientOrderId),
+        42,
+        "Client order ID is stored on the order"
+      );
+
+      const baseBefore = await getAccount(
+        connection,
+        (charlie as any).baseVault
+      );
+      await cancelByClientId("Charlie", 42);
+      const baseAfter = await getAccount(connection, (charlie as any).baseVault);
+
+      assert.equal(
+        Number(baseAfter.amount) - Number(baseBefore.amount),
+        2,
+        "Charlie's 2 base are refunded"
+      );
+      assert.isUndefined(
+        (await getBookOrders(false)).find((o: any) =>
+          o.owner.equals((charlie.wallet as Keypair).publicKey)
+        ),
+        "Charlie's ask is gone"
+      );
+
+      await assertVaultsReconcile();
+    });
+
+    it("Should fail: Bob cancels a client order ID he never used", async () => {
+      try {
+        await cancelByClientId("Bob", 42);
+        assert.fail("Should have thrown error");
+      } catch (err: any) {
+        assert.include(err.message, "OrderNotFound");
+      }
+    });
+  });
+
+  describe("Cancel All Orders Tests", () => {
+    const bobOrders = async (isBid: boolean) => {
+      const bob = users.find((u) => u.name === "Bob")!;
+      return (await getBookOrders(isBid)).filter((o: any) =>
+        o.owner.equals((bob.wallet as Keypair).publicKey)
+      );
+    };
+
+    const cancelAllOrders = async (isBid: boolean | null, limit: number) => {
+      const bob = users.find((u) => u.name === "Bob")!;
+      const bobWallet = bob.wallet as Keypair;
+
+      const tx = await program.methods
+        .cancelAllOrders(isBid, limit)
+        .accounts({
+          signer: bobWallet.publicKey,
+          //@ts-ignore
+          market: marketPda,
+          bids,
+          asks,
+          openOrders: (bob as any).openOrdersPda,
+          baseVault,
+          quoteVault,
+          userBaseVault: (bob as any).baseVault,
+          userQuoteVault: (bob as any).quoteVault,
+        })
+        .signers([bobWallet])
+        .rpc();
+      await connection.confirmTransaction(tx);
+    };
+
+    it("Bob cancels all his bids and keeps his ask", async () => {
+      const bob = users.find((u) => u.name === "Bob")!;
+      await placeOrder("Bob", true, 80, 2);
+      await placeOrder("Bob", true, 85, 1);
+      await placeOrder("Bob", false, 140, 1);
+
+      const quoteBefore = await getAccount(connection, (bob as any).quoteVault);
+      await cancelAllOrders(true, 255);
+      const quoteAfter = await getAccount(connection, (bob as any).quoteVault);
+
+      assert.equal(
+        Number(quoteAfter.amount) - Number(quoteBefore.amount),
+        245,
+        "Both bids are refunded"
+      );
+      assert.lengthOf(await bobOrders(true), 0, "Bob has no bids left");
+      assert.lengthOf(await bobOrders(false), 1, "Bob's ask is kept");
+
+      await assertVaultsReconcile();
+    });
+
+    it("A max count of 1 cancels only Bob's best ask", async () => {
+      await placeOrder("Bob", false, 150, 1);
+
+      await cancelAllOrders(null, 1);
+      const remaining = await bobOrders(false);
+      assert.lengthOf(remaining, 1, "One ask is left");
+      assert.equal(Number(remaining[0].price), 150, "The 140 ask went first");
+
+      await cancelAllOrders(null, 255);
+      assert.lengthOf(await bobOrders(false), 0, "Bob has no asks left");
+
+      await assertVaultsReconcile();
+    });
+  });
+
+  describe("Edit Order Tests", () => {
+    const charlieAsks = async () => {
+      const charlie = users.find((u) => u.name === "Charlie")!;
+      return (await getBookOrders(false)).filter((o: any) =>
+        o.owner.equals((charlie.wallet as Keypair).publicKey)
+      );
+    };
+
+    const editOrder = async (
+      orderId: anchor.BN,
+      price: number,
+      size: number
+    ) => {
+      const charlie = users.find((u) => u.name === "Charlie")!;
+      const charlieWallet = charlie.wallet as Keypair;
+
+      const tx = await program.methods
+        .editOrder(orderId, {
+          isBid: false,
+          price: toPrice(price),
+          size: new anchor.BN(size),
+          orderType: { limit: {} },
+          selfTradeBehavior: { decrementTake: {} },
+          clientOrderId: new anchor.BN(7),
+          expiryTimestamp: new anchor.BN(0),
+        })
+        .accounts({
+          signer: charlieWallet.publicKey,
+          //@ts-ignore
+          market: marketPda,
+          asks,
+          bids,
+          eventQueue,
+          userOpenOrders: (charlie as any).openOrdersPda,
+          baseVault,
+          quoteVault,
+          userBaseVault: (charlie as any).baseVault,
+          userQuoteVault: (charlie as any).quoteVault,
+        })
+        .signers([charlieWallet])
+        .rpc();
+      await connection.confirmTransaction(tx);
+    };
+
+    it("Reducing only the size keeps Charlie's ask in place", async () => {
+      const charlie = users.find((u) => u.name === "Charlie")!;
+      await placeOrder("Charlie", false, 135, 3);
+      const [askBefore] = await charlieAsks();
+      const charlieBefore = await program.account.openOrders.fetch(
+        (charlie as any).openOrdersPda
+      );
+
+      await editOrder(askBefore.orderId, 135, 1);
+
+      const [askAfter] = await charlieAsks();
+      const charlieAfter = await program.account.openOrders.fetch(
+        (charlie as any).openOrdersPda
+      );
+      assert.isTrue(
+        askAfter.orderId.eq(askBefore.orderId),
+        "Order keeps its ID and queue priority"
+      );
+      assert.equal(Number(askAfter.size), 1, "Ask is reduced to 1");
+      assert.equal(
+        Number(charlieAfter.baseFree) - Number(charlieBefore.baseFree),
+        2,
+        "The 2 base no longer offered are freed"
+      );
+
+      await assertVaultsReconcile();
+    });
+
+    it("Repricing replaces Charlie's ask using its locked base", async () => {
+      const charlie = users.find((u) => u.name === "Charlie")!;
+      const [askBefore] = await charlieAsks();
+      const baseBefore = await getAccount(
+        connection,
+        (charlie as any).baseVault
+      );
+      const charlieBefore = await program.account.openOrders.fetch(
+        (charlie as any).openOrdersPda
+      );
+
+      await editOrder(askBefore.orderId, 138, 2);
+
+      const baseAfter = await getAccount(connection, (charlie as any).baseVault);
+      const charlieAfter = await program.account.openOrders.fetch(
+        (charlie as any).openOrdersPda
+      );
+      const asksAfter = await charlieAsks();
+      assert.lengthOf(asksAfter, 1, "The old ask is replaced");
+      assert.equal(Number(asksAfter[0].price), 138, "New ask rests at 138");
+      assert.equal(Number(asksAfter[0].size), 2, "New ask is for 2");
+      assert.equal(
+        Number(charlieBefore.baseFree) - Number(charlieAfter.baseFree),
+        1,
+        "The base the old ask didn't cover comes out of Charlie's free base"
+      );
+      assert.equal(
+        Number(baseBefore.amount),
+        Number(baseAfter.amount),
+        "Nothing is transferred from Charlie's token account"
+      );
+
+      await assertVaultsReconcile();
+      await cancelOrder("Charlie", asksAfter[0].orderId, false);
+    });
+  });
+
+  describe("Order Expiry Tests", () => {
+    const placeExpiringAsk = (userName: string, price: number, ttl: number) =>
+      placeOrder(
+        userName,
+        false,
+        price,
+        1,
+        [],
+        { limit: {} },
+        null,
+        { decrementTake: {} },
+        0,
+        Math.floor(Date.now() / 1000) + ttl
+      );
+
+    const pruneExpiredOrders = async (limit: number) => {
+      const tx = await program.methods
+        .pruneExpiredOrders(limit)
+        .accounts({
+          //@ts-ignore
+          market: marketPda,
+          bids,
+          asks,
+          eventQueue,
+        })
+        .rpc();
+      await connection.confirmTransaction(tx);
+    };
+
+    const ownedAsk = async (userName: string) => {
+      const user = users.find((u) => u.name === userName)!;
+      return (await getBookOrders(false)).find((o: any) =>
+        o.owner.equals((user.wallet as Keypair).publicKey)
+      );
+    };
+
+    it("Should fail: expiry timestamp in the past", async () => {
+      try {
+        await placeExpiringAsk("Bob", 125, -10);
+        assert.fail("Should have thrown error");
+      } catch (err: any) {
+        assert.include(err.message, "InvalidExpiry");
+      }
+    });
+
+    it("Matching skips and removes Bob's expired ask", async () => {
+      const bob = users.find((u) => u.name === "Bob")!;
+      await placeExpiringAsk("Bob", 125, 2);
+      assert.isDefined(await ownedAsk("Bob"), "Bob's ask rests until expiry");
+      await new Promise((resolve) => setTimeout(resolve, 4000));
+
+      const bobBefore = await program.account.openOrders.fetch(
+        (bob as any).openOrdersPda
+      );
+      await placeOrder("Alice", true, 125, 1, ["Bob"], {
+        immediateOrCancel: {},
+      });
+      const bobAfter = await program.account.openOrders.fetch(
+        (bob as any).openOrdersPda
+      );
+
+      assert.isUndefined(await ownedAsk("Bob"), "Expired ask is removed");
+      assert.equal(
+        Number(bobAfter.baseFree) - Number(bobBefore.baseFree),
+        1,
+        "Bob's locked base is released instead of sold"
+      );
+
+      await assertVaultsReconcile();
+    });
+
+    it("Anyone can prune Charlie's expired ask", async () => {
+      const charlie = users.find((u) => u.name === "Charlie")!;
+      await placeExpiringAsk("Charlie", 126, 2);
+      await new Promise((resolve) => setTimeout(resolve, 4000));
+
+      await pruneExpiredOrders(10);
+
+      const charlieBefore = await program.account.openOrders.fetch(
+        (charlie as any).openOrdersPda
+      );
+      await consumeEvents(["Charlie"]);
+      const charlieAfter = await program.account.openOrders.fetch(
+        (charlie as any).openOrdersPda
+      );
+
+      assert.isUndefined(await ownedAsk("Charlie"), "Expired ask is pruned");
+      assert.equal(
+        Number(charlieAfter.baseFree) - Number(charlieBefore.baseFree),
+        1,
+        "Charlie's locked base is released"
+      );
+
+      await assertVaultsReconcile();
+    });
+
+    it("A post-only bid ignores an expired crossing ask", async () => {
+      await placeExpiringAsk("Bob", 127, 2);
+      await new Promise((resolve) => setTimeout(resolve, 4000));
+
+      await placeOrder("Alice", true, 127, 1, [], { postOnly: {} });
+      const aliceBid = (await getBookOrders(true)).find(
+        (o: any) => o.owner.equals(wallet.publicKey) && Number(o.price) === 127
+      );
+      assert.isDefined(aliceBid, "Alice's bid rests at its own price");
+
+      await cancelOrder("Alice", aliceBid.orderId, true);
+      await pruneExpiredOrders(10);
+      await consumeEvents(["Bob"]);
+      assert.isUndefined(await ownedAsk("Bob"), "Bob's expired ask is pruned");
+
+      await assertVaultsReconcile();
+    });
+
+    it("Pruning a large batch stops at 32 orders per call", async () => {
+      const expiringAsks = async () =>
+        (await getBookOrders(false)).filter(
+          (o: any) => !o.expiryTimestamp.isZero()
+        ).length;
+
+      await updateMarketConfig({ maxOrdersPerUser: 32 });
+      for (let i = 0; i < 24; i++) {
+        await placeExpiringAsk("Bob", 400 + i, 8);
+      }
+      for (let i = 0; i < 16; i++) {
+        await placeExpiringAsk("Charlie", 450 + i, 8);
+      }
+      assert.equal(await expiringAsks(), 40);
+      await new Promise((resolve) => setTimeout(resolve, 10000));
+
+      await pruneExpiredOrders(255);
+      assert.equal(await expiringAsks(), 8, "One call prunes 32 orders");
+      await pruneExpiredOrders(255);
+      assert.equal(await expiringAsks(), 0, "The next call prunes the rest");
+
+      await consumeEvents(["Bob", "Charlie"]);
+      await consumeEvents(["Bob", "Charlie"]);
+      await updateMarketConfig({ maxOrdersPerUser: 8 });
+      await assertVaultsReconcile();
+    });
+  });
+
+  describe("Deposit and Withdraw Tests", () => {
+    async function moveFunds(
+      method: "deposit" | "withdraw",
+      userName: string,
+      isBase: boolean,
+      amount: number
+    ) {
+      const user = users.find((u) => u.name === userName)!;
+      const userWallet = user.wallet as Keypair;
+
+      const tx = await program.methods[method](isBase, new anchor.BN(amount))
+        .accounts({
+          signer: userWallet.publicKey,
+          //@ts-ignore
+          market: marketPda,
+          openOrders: (user as any).openOrdersPda,
+          baseVault,
+          quoteVault,
+          userBaseVault: (user as any).baseVault,
+          userQuoteVault: (user as any).quoteVault,
+        })
+        .signers([userWallet])
+        .rpc();
+      await connection.confirmTransaction(tx);
+    }
+
+    it("Should fail: depositing nothing", async () => {
+      try {
+        await moveFunds("deposit", "Charlie", true, 0);
+        assert.fail("Should have thrown error");
+      } catch (err: any) {
+        assert.include(err.message, "InvalidDepositAmount");
+      }
+    });
+
+    it("Charlie's ask is paid for out of deposited base", async () => {
+      const charlie = users.find((u) => u.name === "Charlie")!;
+      const charlieBefore = await program.account.openOrders.fetch(
+        (charlie as any).openOrdersPda
+      );
+      const depositAmount = Math.max(3 - Number(charlieBefore.baseFree), 1);
+
+      await moveFunds("deposit", "Charlie", true, depositAmount);
+      const charlieDeposited = await program.account.openOrders.fetch(
+        (charlie as any).openOrdersPda
+      );
+      assert.equal(
+        Number(charlieDeposited.baseFree) - Number(charlieBefore.baseFree),
+        depositAmount,
+        "Deposited base is credited as free"
+      );
+
+      const baseBefore = await getAccount(
+        connection,
+        (charlie as any).baseVault
+      );
+      await placeOrder("Charlie", false, 140, 3);
+      const baseAfter = await getAccount(connection, (charlie as any).baseVault);
+      const charlieAfter = await program.account.openOrders.fetch(
+        (charlie as any).openOrdersPda
+      );
+
+      assert.equal(
+        Number(baseBefore.amount),
+        Number(baseAfter.amount),
+        "Nothing is transferred for an ask covered by free base"
+      );
+      assert.equal(
+        Number(charlieDeposited.baseFree) - Number(charlieAfter.baseFree),
+        3,
+        "The ask's base comes out of Charlie's free balance"
+      );
+
+      await assertVaultsReconcile();
+      const [ask] = (await getBookOrders(false)).filter((o: any) =>
+        o.owner.equals((charlie.wallet as Keypair).publicKey)
+      );
+      await cancelOrder("Charlie", ask.orderId, false);
+    });
+
+    it("Charlie withdraws all free quote", async () => {
+      const charlie = users.find((u) => u.name === "Charlie")!;
+      await moveFunds("deposit", "Charlie", false, 10);
+      const charlieBefore = await program.account.openOrders.fetch(
+        (charlie as any).openOrdersPda
+      );
+      const quoteBefore = await getAccount(
+        connection,
+        (charlie as any).quoteVault
+      );
+
+      await moveFunds(
+        "withdraw",
+        "Charlie",
+        false,
+        Number(charlieBefore.quoteFree)
+      );
+
+      const quoteAfter = await getAccount(
+        connection,
+        (charlie as any).quoteVault
+      );
+      const charlieAfter = await program.account.openOrders.fetch(
+        (charlie as any).openOrdersPda
+      );
+      assert.equal(Number(charlieAfter.quoteFree), 0, "Free quote is emptied");
+      assert.equal(
+        Number(quoteAfter.amount) - Number(quoteBefore.amount),
+        Number(charlieBefore.quoteFree),
+        "Free quote is returned to Charlie's token account"
+      );
+
+      await assertVaultsReconcile();
+    });
+  });
+
+  describe("Settle All Tests", () => {
+    const signerOf = (user: any): Keypair =>
+      user.wallet instanceof Keypair ? user.wallet : user.wallet.payer;
+
+    const settleAccounts = (user: any) => ({
+      signer: signerOf(user).publicKey,
+      //@ts-ignore
+      market: marketPda,
+      openOrders: user.openOrdersPda,
+      baseVault,
+      quoteVault,
+      userBaseVault: user.baseVault,
+      userQuoteVault: user.quoteVault,
+    });
+
+    it("Alice settles all free base by passing u64::MAX", async () => {
+      const alice = users.find((u) => u.name === "Alice")!;
+      const aliceBefore = await program.account.openOrders.fetch(
+        (alice as any).openOrdersPda
+      );
+      const baseBefore = await getAccount(connection, (alice as any).baseVault);
+
+      const tx = await program.methods
+        .settleFunds(true, new anchor.BN("18446744073709551615"))
+        .accounts(settleAccounts(alice))
+        .signers([signerOf(alice)])
+        .rpc();
+      await connection.confirmTransaction(tx);
+
+      const aliceAfter = await program.account.openOrders.fetch(
+        (alice as any).openOrdersPda
+      );
+      const baseAfter = await getAccount(connection, (alice as any).baseVault);
+      assert.equal(Number(aliceAfter.baseFree), 0, "Free base is emptied");
+      assert.equal(
+        Number(baseAfter.amount) - Number(baseBefore.amount),
+        Number(aliceBefore.baseFree),
+        "All of Alice's free base is transferred"
+      );
+      assert.equal(
+        Number(aliceAfter.quoteFree),
+        Number(aliceBefore.quoteFree),
+        "Free quote is left alone"
+      );
+
+      await assertVaultsReconcile();
+    });
+
+    it("Bob settles free base and quote in one call", async () => {
+      const bob = users.find((u) => u.name === "Bob")!;
+      const bobBefore = await program.account.openOrders.fetch(
+        (bob as any).openOrdersPda
+      );
+      const baseBefore = await getAccount(connection, (bob as any).baseVault);
+      const quoteBefore = await getAccount(connection, (bob as any).quoteVault);
+
+      const tx = await program.methods
+        .settleAll()
+        .accounts(settleAccounts(bob))
+        .signers([signerOf(bob)])
+        .rpc();
+      await connection.confirmTransaction(tx);
+
+      const bobAfter = await program.account.openOrders.fetch(
+        (bob as any).openOrdersPda
+      );
+      const baseAfter = await getAccount(connection, (bob as any).baseVault);
+      const quoteAfter = await getAccount(connection, (bob as any).quoteVault);
+      assert.equal(Number(bobAfter.baseFree), 0, "Free base is emptied");
+      assert.equal(Number(bobAfter.quoteFree), 0, "Free quote is emptied");
+      assert.equal(
+        Number(baseAfter.amount) - Number(baseBefore.amount),
+        Number(bobBefore.baseFree),
+        "Bob receives all free base"
+      );
+      assert.equal(
+        Number(quoteAfter.amount) - Number(quoteBefore.amount),
+        Number(bobBefore.quoteFree),
+        "Bob receives all free quote"
+      );
+
+      await assertVaultsReconcile();
+    });
+
+    it("Should fail: settling all with nothing free", async () => {
+      const bob = users.find((u) => u.name === "Bob")!;
+      try {
+        await program.methods
+          .settleAll()
+          .accounts(settleAccounts(bob))
+          .signers([signerOf(bob)])
+          .rpc();
+        assert.fail("Should have thrown error");
+      } catch (err: any) {
+        assert.include(err.message, "NoFundsToSettle");
+      }
+    });
+  });
+
+  describe("Event Tests", () => {
+    it("Placing, filling and cancelling emit sequenced events", async () => {
+      const bob = users.find((u) => u.name === "Bob")!;
+      const bobPubkey = (bob.wallet as Keypair).publicKey;
+
+      const placeTx = await placeOrder("Bob", false, 150, 2);
+      const [placed] = await txEvents(placeTx);
+      assert.equal(placed.name, "orderPlaced");
+      assert.isTrue(placed.data.market.equals(marketPda));
+      assert.isTrue(placed.data.owner.equals(bobPubkey));
+      assert.equal(Number(placed.data.size), 2);
+
+      const fillTx = await placeOrder("Alice", true, 150, 1, ["Bob"]);
+      const [filled] = await txEvents(fillTx);
+      assert.equal(filled.name, "orderFilled");
+      assert.isTrue(filled.data.maker.equals(bobPubkey));
+      assert.isTrue(filled.data.makerOrderId.eq(placed.data.orderId));
+      assert.equal(Number(filled.data.price), 150);
+      assert.equal(Number(filled.data.size), 1);
+      assert.equal(
+        Number(filled.data.seqNum),
+        Number(placed.data.seqNum) + 1,
+        "Sequence numbers have no gaps"
+      );
+
+      const cancelTx = await cancelOrder("Bob", placed.data.orderId, false);
+      const [cancelled] = await txEvents(cancelTx);
+      assert.equal(cancelled.name, "orderCancelled");
+      assert.isTrue(cancelled.data.orderId.eq(placed.data.orderId));
+      assert.equal(Number(cancelled.data.size), 1, "Only 1 base was left");
+      assert.equal(
+        Number(cancelled.data.seqNum),
+        Number(filled.data.seqNum) + 1
+      );
+
+      await assertVaultsReconcile();
+    });
+  });
+
+  describe("Close OpenOrders Tests", () => {
+    async function closeOpenOrders(userName: string, destination: PublicKey) {
+      const user = users.find((u) => u.name === userName)!;
+      const userWallet = user.wallet as Keypair;
+
+      const tx = await program.methods
+        .closeOpenOrders()
+        .accounts({
+          signer: userWallet.publicKey,
+          //@ts-ignore
+          market: marketPda,
+          openOrders: (user as any).openOrdersPda,
+          destination,
+        })
+        .signers([userWallet])
+        .rpc();
+      await connection.confirmTransaction(tx);
+    }
+
+    it("Should fail: closing Bob's OpenOrders with a free balance", async () => {
+      try {
+        await closeOpenOrders("Bob", Keypair.generate().publicKey);
+        assert.fail("Should have thrown error");
+      } catch (err: any) {
+        assert.include(err.message, "OpenOrdersNotEmpty");
+      }
+    });
+
+    it("Bob settles everything and closes OpenOrders to another account", async () => {
+      const bob = users.find((u) => u.name === "Bob")!;
+      const bobWallet = bob.wallet as Keypair;
+      const destination = Keypair.generate().publicKey;
+
+      const settleTx = await program.methods
+        .settleAll()
+        .accounts({
+          signer: bobWallet.publicKey,
+          //@ts-ignore
+          market: marketPda,
+          openOrders: (bob as any).openOrdersPda,
+          baseVault,
+          quoteVault,
+          userBaseVault: (bob as any).baseVault,
+          userQuoteVault: (bob as any).quoteVault,
+        })
+        .signers([bobWallet])
+        .rpc();
+      await connection.confirmTransaction(settleTx);
+
+      const rent = await connection.getBalance((bob as any).openOrdersPda);
+      await closeOpenOrders("Bob", destination);
+
+      assert.isNull(
+        await connection.getAccountInfo((bob as any).openOrdersPda),
+        "Bob's OpenOrders account is closed"
+      );
+      assert.equal(
+        await connection.getBalance(destination),
+        rent,
+        "The rent goes to the chosen destination"
+      );
+
+      await assertVaultsReconcile();
+    });
+  });
+
+  describe("Open Order Slot Tests", () => {
+    const bobSlots = async () => {
+      const bob = users.find((u) => u.name === "Bob")!;
+      const openOrders = await program.account.openOrders.fetch(
+        (bob as any).openOrdersPda
+      );
+      return openOrders.orders.filter((slot: any) => !slot.orderId.isZero());
+    };
+
+    it("Bob's OpenOrders tracks his resting bid", async () => {
+      await placeOrder(
+        "Bob",
+        true,
+        70,
+        1,
+        [],
+        { limit: {} },
+        null,
+        { decrementTake: {} },
+        7
+      );
+
+      const [bid] = await getBookOrders(true);
+      const slots = await bobSlots();
+      assert.lengthOf(slots, 1, "One order is tracked");
+      assert.isTrue(slots[0].orderId.eq(bid.orderId), "Slot holds the bid");
+      assert.isTrue(slots[0].isBid);
+      assert.equal(Number(slots[0].clientOrderId), 7);
+    });
+
+    it("Bob's slot is freed once his filled bid's event is consumed", async () => {
+      await placeOrder("Alice", false, 70, 1, ["Bob"]);
+
+      assert.lengthOf(await bobSlots(), 0, "No orders are tracked");
+      await assertVaultsReconcile();
+    });
+
+    it("Should fail: Bob places more orders than the market allows", async () => {
+      const market = await program.account.market.fetch(marketPda);
+      for (let i = 0; i < market.maxOrdersPerUser; i++) {
+        await placeOrder("Bob", false, 200 + i, 1);
+      }
+
+      try {
+        await placeOrder("Bob", false, 250, 1);
+        assert.fail("Should have thrown error");
+      } catch (err: any) {
+        assert.include(err.message, "TooManyOpenOrders");
+      }
+
+      for (const slot of await bobSlots()) {
+        await cancelOrder("Bob", slot.orderId, slot.isBid);
+      }
+      assert.lengthOf(await bobSlots(), 0, "Cancelling frees every slot");
+      assert.lengthOf(await getBookOrders(false), 0, "Bob has no asks left");
+
+      await assertVaultsReconcile();
+    });
+  });
+
+  describe("Market Config Tests", () => {
+    it("Should fail: Bob isn't the market authority", async () => {
+      const bob = users.find((u) => u.name === "Bob")!;
+      try {
+        await updateMarketConfig({ takerFeeBps: 1 }, bob.wallet as Keypair);
+        assert.fail("Should have thrown error");
+      } catch (err: any) {
+        assert.include(err.message, "UnauthorizedAccess");
+      }
+    });
+
+    it("Should fail: taker fee above 100%", async () => {
+      try {
+        await updateMarketConfig({ takerFeeBps: 10_001 });
+        assert.fail("Should have thrown error");
+      } catch (err: any) {
+        assert.include(err.message, "InvalidFeeConfig");
+      }
+    });
+
+    it("Should fail: changing tick or lot sizes with an order resting", async () => {
+      await placeOrder("Bob", false, 300, 1);
+      try {
+        await updateMarketConfig({ baseLotSize: new anchor.BN(10) });
+        assert.fail("Should have thrown error");
+      } catch (err: any) {
+        assert.include(err.message, "MarketNotEmpty");
+      }
+
+      const market = await program.account.market.fetch(marketPda);
+      try {
+        await updateMarketConfig({ tickSize: market.tickSize.muln(2) });
+        assert.fail("Should have thrown error");
+      } catch (err: any) {
+        assert.include(err.message, "MarketNotEmpty");
+      }
+    });
+
+    it("A cancel-only market rejects orders but allows cancels", async () => {
+      const tx = await updateMarketConfig({ status: { cancelOnly: {} } });
+      const txInfo = await connection.getTransaction(tx, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      const parser = new anchor.EventParser(program.programId, program.coder);
+      const [updated] = Array.from(
+        parser.parseLogs(txInfo!.meta!.logMessages!)
+      );
+      assert.equal(updated.name, "marketConfigUpdated");
+      assert.deepEqual(updated.data.oldConfig.status, { active: {} });
+      assert.deepEqual(updated.data.newConfig.status, { cancelOnly: {} });
+
+      try {
+        await placeOrder("Bob", false, 301, 1);
+        assert.fail("Should have thrown error");
+      } catch (err: any) {
+        assert.include(err.message, "MarketNotActive");
+      }
+
+      const [ask] = await getBookOrders(false);
+      await cancelOrder("Bob", ask.orderId, false);
+      assert.lengthOf(await getBookOrders(false), 0, "Bob's ask is cancelled");
+
+      await updateMarketConfig({ status: { active: {} } });
+      await assertVaultsReconcile();
+    });
+  });
+
+  describe("Authority Transfer Tests", () => {
+    const proposeAuthority = async (
+      signer: Keypair,
+      newAuthority: PublicKey
+    ) => {
+      const tx = await program.methods
+        .proposeAuthority(newAuthority)
+        .accounts({
+          authority: signer.publicKey,
+          //@ts-ignore
+          market: marketPda,
+        })
+        .signers([signer])
+        .rpc();
+      await connection.confirmTransaction(tx);
+    };
+
+    const acceptAuthority = async (signer: Keypair) => {
+      const tx = await program.methods
+        .acceptAuthority()
+        .accounts({
+          newAuthority: signer.publicKey,
+          //@ts-ignore
+          market: marketPda,
+        })
+        .signers([signer])
+        .rpc();
+      await connection.confirmTransaction(tx);
+    };
+
+    it("Should fail: Charlie accepts authority proposed to Bob", async () => {
+      const bob = users.find((u) => u.name === "Bob")!;
+      const charlie = users.find((u) => u.name === "Charlie")!;
+      await proposeAuthority(wallet.payer, (bob.wallet as Keypair).publicKey);
+
+      const market = await program.account.market.fetch(marketPda);
+      assert.isTrue(
+        market.pendingAuthority.equals((bob.wallet as Keypair).publicKey),
+        "Bob is the pending authority"
+      );
+      assert.isTrue(
+        market.authority.equals(wallet.publicKey),
+        "Authority is unchanged until accepted"
+      );
+
+      try {
+        await acceptAuthority(charlie.wallet as Keypair);
+        assert.fail("Should have thrown error");
+      } catch (err: any) {
+        assert.include(err.message, "UnauthorizedAccess");
+      }
+    });
+
+    it("Bob accepts the market authority and hands it back", async () => {
+      const bob = users.find((u) => u.name === "Bob")!;
+      const bobWallet = bob.wallet as Keypair;
+
+      await acceptAuthority(bobWallet);
+      let market = await program.account.market.fetch(marketPda);
+      assert.isTrue(market.authority.equals(bobWallet.publicKey));
+      assert.isTrue(
+        market.pendingAuthority.equals(PublicKey.default),
+        "The proposal is cleared"
+      );
+
+      try {
+        await proposeAuthority(wallet.payer, wallet.publicKey);
+        assert.fail("Should have thrown error");
+      } catch (err: any) {
+        assert.include(err.message, "UnauthorizedAccess");
+      }
+
+      await proposeAuthority(bobWallet, wallet.publicKey);
+      await acceptAuthority(wallet.payer);
+      market = await program.account.market.fetch(marketPda);
+      assert.isTrue(market.authority.equals(wallet.publicKey));
+    });
+  });
+
+  describe("Matching Priority Tests", () => {
+    const quoteFree = async (userName: string) => {
+      const user = users.find((u) => u.name === userName)!;
+      const openOrders = await program.account.openOrders.fetch(
+        (user as any).openOrdersPda
+      );
+      return Number(openOrders.quoteFree);
+    };
+
+    it("A taker fills the earliest order at the best price first", async () => {
+      await placeOrder("Bob", false, 160, 1);
+      await placeOrder("Charlie", false, 155, 1);
+      await placeOrder("Bob", false, 155, 1);
+
+      const charlieBefore = await quoteFree("Charlie");
+      const bobBefore = await quoteFree("Bob");
+      await placeOrder("Alice", true, 160, 1, ["Charlie", "Bob"], {
+        immediateOrCancel: {},
+      });
+
+      assert.equal(
+        (await quoteFree("Charlie")) - charlieBefore,
+        155,
+        "Charlie's 155 ask was placed first at the best price"
+      );
+      assert.equal((await quoteFree("Bob")) - bobBefore, 0);
+      assert.sameMembers(
+        (await getBookOrders(false)).map((o: any) => Number(o.price)),
+        [155, 160],
+        "Bob's asks are left"
+      );
+    });
+
+    it("A better price beats an earlier order", async () => {
+      const bobBefore = await quoteFree("Bob");
+      await placeOrder("Alice", true, 160, 1, ["Bob"], {
+        immediateOrCancel: {},
+      });
+
+      assert.equal(
+        (await quoteFree("Bob")) - bobBefore,
+        155,
+        "Bob's later 155 ask fills before his earlier 160 ask"
+      );
+      const asks = await getBookOrders(false);
+      assert.deepEqual(
+        asks.map((o: any) => Number(o.price)),
+        [160],
+        "Only the 160 ask is left"
+      );
+
+      await assertVaultsReconcile();
+      await cancelOrder("Bob", asks[0].orderId, false);
+    });
+  });
+
+  describe("Order ID Tests", () => {
+    it("Orders placed in the same slot get distinct IDs", async () => {
+      const charlie = users.find((u) => u.name === "Charlie")!;
+      const charlieWallet = charlie.wallet as Keypair;
+
+      const askInstruction = (clientOrderId: number) =>
+        program.methods
+          .placeOrder({
+            isBid: false,
+            price: toPrice(170),
+            size: new anchor.BN(1),
+            orderType: { limit: {} },
+            selfTradeBehavior: { decrementTake: {} },
+            clientOrderId: new anchor.BN(clientOrderId),
+            expiryTimestamp: new anchor.BN(0),
+          })
+          .accounts({
+            signer: charlieWallet.publicKey,
+            //@ts-ignore
+            market: marketPda,
+            asks,
+            bids,
+            eventQueue,
+            userOpenOrders: (charlie as any).openOrdersPda,
+            baseVault,
+            quoteVault,
+            userBaseVault: (charlie as any).baseVault,
+            userQuoteVault: (charlie as any).quoteVault,
+            referrer: null,
+          })
+          .instruction();
+
+      // One transaction, so both orders share a slot and a timestamp
+      await provider.sendAndConfirm(
+        new Transaction().add(await askInstruction(1), await askInstruction(2)),
+        [charlieWallet]
+      );
+
+      const orders = await getBookOrders(false);
+      const first = orders.find((o: any) => Number(o.clientOrderId) === 1);
+      const second = orders.find((o: any) => Number(o.clientOrderId) === 2);
+      assert.equal(Number(first.timestamp), Number(second.timestamp));
+      assert.isFalse(first.orderId.eq(second.orderId), "Order IDs differ");
+      assert.isTrue(
+        first.orderId.lt(second.orderId),
+        "The first ask keeps time priority"
+      );
+
+      await cancelOrder("Charlie", first.orderId, false);
+      const remaining = await getBookOrders(false);
+      assert.lengthOf(remaining, 1, "Only the targeted ask is cancelled");
+      assert.isTrue(remaining[0].orderId.eq(second.orderId));
+
+      await assertVaultsReconcile();
+      await cancelOrder("Charlie", second.orderId, false);
+    });
+  });
+
+  describe("Fee Charging Tests", () => {
+    // `quote` is a user's wallet plus free quote, so it is unaffected by
+    // where an order was funded from
+    const balances = async (userName: string) => {
+      const user = users.find((u) => u.name === userName)!;
+      const openOrders = await program.account.openOrders.fetch(
+        (user as any).openOrdersPda
+      );
+      const quoteWallet = await getAccount(
+        connection,
+        (user as any).quoteVault
+      );
+      return {
+        quote: Number(quoteWallet.amount) + Number(openOrders.quoteFree),
+        quoteFree: Number(openOrders.quoteFree),
+        quoteLocked: Number(openOrders.quoteLocked),
+        baseFree: Number(openOrders.baseFree),
+        baseLocked: Number(openOrders.baseLocked),
+      };
+    };
+    const feesAccrued = async () =>
+      Number((await program.account.market.fetch(marketPda)).feesAccrued);
+
+    let feesBefore: number;
+
+    before(async () => {
+      feesBefore = await feesAccrued();
+      await updateMarketConfig({ takerFeeBps: 30, makerFeeBps: -20 });
+    });
+
+    it("Alice's buy pays the taker fee and Bob's ask earns the rebate", async () => {
+      await placeOrder("Bob", false, 501, 3);
+      const aliceBefore = await balances("Alice");
+      const bobBefore = await balances("Bob");
+
+      await placeOrder("Alice", true, 501, 3, ["Bob"], {
+        immediateOrCancel: {},
+      });
+
+      const aliceAfter = await balances("Alice");
+      const bobAfter = await balances("Bob");
+      // 1503 quote traded: the 4.509 taker fee rounds up to 5, and the
+      // 1.002 per-lot rebate rounds down to 1
+      assert.equal(aliceBefore.quote - aliceAfter.quote, 1508);
+      assert.equal(aliceAfter.baseFree - aliceBefore.baseFree, 3);
+      assert.equal(bobAfter.quoteFree - bobBefore.quoteFree, 1506);
+      assert.equal(bobBefore.baseLocked - bobAfter.baseLocked, 3);
+      assert.equal((await feesAccrued()) - feesBefore, 2);
+
+      await assertVaultsReconcile();
+    });
+
+    it("Alice's sell pays the taker fee and Charlie's bid earns the rebate", async () => {
+      await placeOrder("Charlie", true, 550, 3);
+      const aliceBefore = await balances("Alice");
+      const charlieBefore = await balances("Charlie");
+      const feesBeforeFill = await feesAccrued();
+
+      await placeOrder("Alice", false, 550, 2, ["Charlie"], {
+        immediateOrCancel: {},
+      });
+
+      const aliceAfter = await balances("Alice");
+      const charlieAfter = await balances("Charlie");
+      // 1100 quote traded: a 3.3 taker fee rounds up to 4, a 1.1 per-lot
+      // rebate down to 1
+      assert.equal(aliceAfter.quoteFree - aliceBefore.quoteFree, 1096);
+      assert.equal(charlieBefore.quoteLocked - charlieAfter.quoteLocked, 1100);
+      assert.equal(charlieAfter.quoteFree - charlieBefore.quoteFree, 2);
+      assert.equal(charlieAfter.baseFree - charlieBefore.baseFree, 2);
+      assert.equal((await feesAccrued()) - feesBeforeFill, 2);
+
+      await assertVaultsReconcile();
+    });
+
+    it("A rebate never exceeds the taker fee on its fill", async () => {
+      // Charlie's last lot still rests at -20 bps
+      await updateMarketConfig({ takerFeeBps: 0, makerFeeBps: 0 });
+      const aliceBefore = await balances("Alice");
+      const charlieBefore = await balances("Charlie");
+      const feesBeforeFill = await feesAccrued();
+
+      await placeOrder("Alice", false, 550, 1, ["Charlie"], {
+        immediateOrCancel: {},
+      });
+
+      const aliceAfter = await balances("Alice");
+      const charlieAfter = await balances("Charlie");
+      assert.equal(aliceAfter.quoteFree - aliceBefore.quoteFree, 550);
+      assert.equal(
+        charlieAfter.quoteFree,
+        charlieBefore.quoteFree,
+        "No rebate is paid out of a zero taker fee"
+      );
+      assert.equal(charlieBefore.quoteLocked - charlieAfter.quoteLocked, 550);
+      assert.equal(await feesAccrued(), feesBeforeFill);
+
+      await assertVaultsReconcile();
+    });
+
+    it("A resting bid locks its maker fee and pays it when filled", async () => {
+      await updateMarketConfig({ takerFeeBps: 30, makerFeeBps: 10 });
+      const charlieBefore = await balances("Charlie");
+
+      await placeOrder("Charlie", true, 501, 3);
+      const charlieResting = await balances("Charlie");
+      // 0.501 quote per lot rounds up to a 1 quote fee per lot
+      assert.equal(
+        charlieResting.quoteLocked - charlieBefore.quoteLocked,
+        1506,
+        "Charlie locks 1503 quote plus a 3 quote maker fee"
+      );
+
+      const aliceBefore = await balances("Alice");
+      const feesBeforeFill = await feesAccrued();
+      await placeOrder("Alice", false, 501, 3, ["Charlie"], {
+        immediateOrCancel: {},
+      });
+
+      const aliceAfter = await balances("Alice");
+      const charlieAfter = await balances("Charlie");
+      assert.equal(aliceAfter.quoteFree - aliceBefore.quoteFree, 1498);
+      assert.equal(charlieAfter.quoteLocked, charlieBefore.quoteLocked);
+      assert.equal(charlieAfter.quoteFree, charlieResting.quoteFree);
+      assert.equal(charlieAfter.baseFree - charlieResting.baseFree, 3);
+      assert.equal((await feesAccrued()) - feesBeforeFill, 8);
+
+      await assertVaultsReconcile();
+    });
+
+    it("The authority collects the accrued fees into a treasury", async () => {
+      const bob = users.find((u) => u.name === "Bob")!;
+      const fees = await feesAccrued();
+      assert.equal(fees - feesBefore, 12, "Fees from all four fills");
+
+      const treasuryBefore = await getAccount(
+        connection,
+        (bob as any).quoteVault
+      );
+      await assertVaultsReconcile();
+      await collectFees("Alice", (bob as any).quoteVault);
+      const treasuryAfter = await getAccount(
+        connection,
+        (bob as any).quoteVault
+      );
+
+      assert.equal(
+        Number(treasuryAfter.amount) - Number(treasuryBefore.amount),
+        fees
+      );
+      assert.equal(await feesAccrued(), 0, "Nothing is left to collect");
+      await assertVaultsReconcile();
+    });
+
+    after(async () => {
+      await updateMarketConfig({ takerFeeBps: 0, makerFeeBps: 0 });
+    });
+  });
+
+  describe("Referrer Fee Tests", () => {
+    const feesAccrued = async () =>
+      Number((await program.account.market.fetch(marketPda)).feesAccrued);
+    const referrerBalance = async () => {
+      const charlie = users.find((u) => u.name === "Charlie")!;
+      return Number(
+        (await getAccount(connection, (charlie as any).quoteVault)).amount
+      );
+    };
+
+    // Bob rests an ask that Alice buys, referred by Charlie
+    const referredBuy = async () => {
+      const charlie = users.find((u) => u.name === "Charlie")!;
+      await placeOrder("Bob", false, 501, 3);
+      return await placeOrder(
+        "Alice",
+        true,
+        501,
+        3,
+        ["Bob"],
+        { immediateOrCancel: {} },
+        (charlie as any).quoteVault
+      );
+    };
+
+    it("The referrer is paid its share of the taker fee", async () => {
+      await updateMarketConfig({
+        takerFeeBps: 30,
+        makerFeeBps: 0,
+        referrerShareBps: 5000,
+      });
+      const referrerBefore = await referrerBalance();
+      const feesBefore = await feesAccrued();
+
+      const tx = await referredBuy();
+
+      // The 1503 quote fill pays a 5 quote taker fee, and half of it
+      // rounds down to 2
+      assert.equal((await referrerBalance()) - referrerBefore, 2);
+      assert.equal(
+        (await feesAccrued()) - feesBefore,
+        3,
+        "The payout comes out of the fees accrued"
+      );
+
+      const paid = (await txEvents(tx)).find(
+        (e) => e.name === "referrerFeePaid"
+      )!;
+      const charlie = users.find((u) => u.name === "Charlie")!;
+      assert.isTrue(paid.data.market.equals(marketPda));
+      assert.isTrue(paid.data.referrer.equals((charlie as any).quoteVault));
+      assert.isTrue(paid.data.taker.equals(wallet.publicKey));
+      assert.equal(Number(paid.data.amount), 2);
+
+      await assertVaultsReconcile();
+    });
+
+    it("The referrer payout is capped at what the order left in fees", async () => {
+      await updateMarketConfig({
+        takerFeeBps: 30,
+        makerFeeBps: -20,
+        referrerShareBps: 10_000,
+      });
+      const referrerBefore = await referrerBalance();
+      const feesBefore = await feesAccrued();
+
+      const tx = await referredBuy();
+
+      // Bob's 3 quote rebate leaves 2 of the 5 quote taker fee, so the
+      // referrer gets 2 rather than its full share of 5
+      assert.equal((await referrerBalance()) - referrerBefore, 2);
+      assert.equal(await feesAccrued(), feesBefore, "Nothing is left accrued");
+
+      const paid = (await txEvents(tx)).find(
+        (e) => e.name === "referrerFeePaid"
+      )!;
+      assert.equal(Number(paid.data.amount), 2);
+
+      await assertVaultsReconcile();
+    });
+
+    after(async () => {
+      await updateMarketConfig({
+        takerFeeBps: 0,
+        makerFeeBps: 0,
+        referrerShareBps: 0,
+      });
+    });
+  });
+
+  // Helper function to get the resting orders on one side of the book
+  async function getBookOrders(isBid: boolean) {
+    const orderBook = await program.account.orderBook.fetch(
+      isBid ? bids : asks
+    );
+    // Unused slots in the zero-copy book have zero size
+    return orderBook.orders.filter((o: any) => !o.size.isZero());
+  }
+
+  // Every token held by the market must be owed to some user, either as a
+  // free balance or locked behind a resting order, or be uncollected fees
+  async function assertVaultsReconcile() {
+    const marketAccount = await program.account.market.fetch(marketPda);
+    let owedBase = 0;
+    let owedQuote = Number(marketAccount.feesAccrued);
+    for (const u of users) {
+      try {
+        const openOrders = await program.account.openOrders.fetch(
+          (u as any).openOrdersPda
+        );
+        owedBase += Number(openOrders.baseFree) + Number(openOrders.baseLocked);
+        owedQuote +=
+          Number(openOrders.quoteFree) + Number(openOrders.quoteLocked);
+      } catch {}
+    }
+
+    const baseVaultAcc = await getAccount(connection, baseVault);
+    const quoteVaultAcc = await getAccount(connection, quoteVault);
+    assert.equal(
+      Number(baseVaultAcc.amount),
+      owedBase,
+      "Base vault should equal total base owed to users"
+    );
+    assert.equal(
+      Number(quoteVaultAcc.amount),
+      owedQuote,
+      "Quote vault should equal total quote owed to users"
+    );
+  }
+
+  // Decodes the program events logged by a confirmed transaction
+  async function txEvents(signature: string) {
+    const tx = await connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const parser = new anchor.EventParser(program.programId, program.coder);
+    return Array.from(parser.parseLogs(tx!.meta!.logMessages!));
+  }
+
+  // Applies `changes` on top of the market's current config
+  async function updateMarketConfig(changes: any, signer = wallet.payer) {
+    const market = await program.account.market.fetch(marketPda);
+    const config = {
+      status: market.status,
+      bookFullPolicy: market.bookFullPolicy,
+      tickSize: market.tickSize,
+      baseLotSize: market.baseLotSize,
+      quoteLotSize: market.quoteLotSize,
+      minBaseOrderSize: market.minBaseOrderSize,
+      maxOrdersPerUser: market.maxOrdersPerUser,
+      makerFeeBps: market.makerFeeBps,
+      takerFeeBps: market.takerFeeBps,
+      referrerShareBps: market.referrerShareBps,
+      ...changes,
+    };
+
+    const tx = await program.methods
+      .updateMarketConfig(config)
+      .accounts({
+        authority: signer.publicKey,
+        //@ts-ignore
+        market: marketPda,
+        bids,
+        asks,
+        eventQueue,
+      })
+      .signers([signer])
+      .rpc();
+    await connection.confirmTransaction(tx);
+    return tx;
+  }
+
+  async function collectFees(authorityName: string, treasury: PublicKey) {
+    const authority = users.find((u) => u.name === authorityName)!;
+    const authorityWallet =
+      authority.wallet instanceof Keypair
+        ? authority.wallet
+        : authority.wallet.payer;
+
+    const tx = await program.methods
+      .collectFees()
+      .accounts({
+        authority: authorityWallet.publicKey,
+        //@ts-ignore
+        market: marketPda,
+        quoteVault,
+        treasury,
+      })
+      .signers([authorityWallet])
+      .rpc();
+    await connection.confirmTransaction(tx);
+  }
+
+  async function placeOrder(
+    userName: string,
+    isBid: boolean,
+    price: number | anchor.BN, // raw price, or an exact fixed-point price
+    size: number,
+    matchedUsers: string[] = [],
+    orderType: any = { limit: {} },
+    referrer: PublicKey | null = null,
+    selfTradeBehavior: any = { decrementTake: {} },
+    clientOrderId = 0,
+    expiryTimestamp = 0
+  ) {
+    const user = users.find((u) => u.name === userName)!;
+    const userWallet =
+      user.wallet instanceof Keypair ? user.wallet : user.wallet.payer;
+    const userPubkey =
+      user.wallet instanceof Keypair
+        ? user.wallet.publicKey
+        : (user.wallet as anchor.Wallet).publicKey;
+
+    const tx = await program.methods
+      .placeOrder({
+        isBid,
+        price: anchor.BN.isBN(price) ? price : toPrice(price),
+        size: new anchor.BN(size),
+        orderType,
+        selfTradeBehavior,
+        clientOrderId: new anchor.BN(clientOrderId),
+        expiryTimestamp: new anchor.BN(expiryTimestamp),
+      })
+      .accounts({
+        signer: userPubkey,
+        //@ts-ignore
+        market: marketPda,
+        asks,
+        bids,
+        eventQueue,
+        userOpenOrders: (user as any).openOrdersPda,
+        baseVault,
+        quoteVault,
+        userBaseVault: (user as any).baseVault,
+        userQuoteVault: (user as any).quoteVault,
+        referrer,
+      })
+      .signers([userWallet])
+      .rpc();
+    await connection.confirmTransaction(tx);
+
+    if (matchedUsers.length > 0) {
+      await consumeEvents(matchedUsers);
+    }
+    return tx;
+  }
+
+  async function cancelOrder(
+    userName: string,
+    orderId: anchor.BN,
+    isBid: boolean
+  ) {
+    const user = users.find((u) => u.name === userName)!;
+    const userWallet =
+      user.wallet instanceof Keypair ? user.wallet : user.wallet.payer;
+
+    const tx = await program.methods
+      .cancelOrder(orderId, isBid)
+      .accounts({
+        signer: userWallet.publicKey,
+        //@ts-ignore
+        market: marketPda,
+        bids,
+        asks,
+        openOrders: (user as any).openOrdersPda,
+        baseVault,
+        quoteVault,
+        userBaseVault: (user as any).baseVault,
+        userQuoteVault: (user as any).quoteVault,
+      })
+      .signers([userWallet])
+      .rpc();
+    await connection.confirmTransaction(tx);
+    return tx;
+  }
+
+  // Cranks the event queue, passing the OpenOrders of every maker involved
+  async function consumeEvents(makerNames: string[]) {
+    const remainingAccounts = makerNames.map((name) => ({
+      pubkey: (users.find((u) => u.name === name) as any).openOrdersPda,
+      isSigner: false,
+      isWritable: true,
+    }));
+
+    const tx = await program.methods
+      .consumeEvents(32)
+      .accounts({
+        //@ts-ignore
+        market: marketPda,
+        eventQueue,
+      })
+      .remainingAccounts(remainingAccounts)
+      .rpc();
+    await connection.confirmTransaction(tx);
+  }
+
   async function logUserOpenOrdersState(userName: string, userPda: PublicKey) {
     try {
       const openOrdersAccount = await program.account.openOrders.fetch(userPda);